@@ -1,7 +1,7 @@
 use clap::Parser;
 use clap_serde_derive::clap;
 
-use crate::{config_parsing::WithConfigFile, coverage, generate};
+use crate::{config_parsing::WithConfigFile, coverage, explain, generate, report, show};
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -14,9 +14,33 @@ pub struct Config {
 pub enum Subcommands {
     /// Run coverage on a Solana project
     #[command(alias = "c")]
-    Coverage(WithConfigFile<coverage::Config>),
+    Coverage(Box<WithConfigFile<coverage::Config>>),
+
+    /// Regenerate the coverage reports from existing profile data, without rebuilding or re-running the tests
+    #[command(alias = "r")]
+    Report(Box<WithConfigFile<report::Config>>),
 
     /// Generate Solana projects and tests
     #[command(alias = "g")]
     Generate(generate::Config),
+
+    /// List the tests covering a line or function (requires a `coverage --per-test` run)
+    #[command(alias = "e")]
+    Explain(explain::Config),
+
+    /// Print a source file annotated with its coverage (hits, uncovered lines and branches)
+    #[command(alias = "s")]
+    Show(Box<show::Config>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::CommandFactory;
+
+    #[test]
+    fn clap_debug_assert() {
+        Config::command().debug_assert();
+    }
 }
@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::{bail, Context};
+use grcov::ResultTuple;
+use serde::{Deserialize, Serialize};
+use tabled::{Table, Tabled};
+
+use super::stats::{Counter, Stats};
+
+/// Machine-readable per-file/per-function coverage summary, used to detect regressions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub version: u32,
+    pub total: Stats,
+    /// Keyed by the path relative to the project root
+    pub files: BTreeMap<PathBuf, FileBaseline>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileBaseline {
+    pub stats: Stats,
+    /// Whether each (contract) function was executed
+    pub functions: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Clone, Tabled)]
+pub struct Regression {
+    #[tabled(rename = "Subject")]
+    pub subject: String,
+    #[tabled(rename = "Metric")]
+    pub metric: &'static str,
+    #[tabled(rename = "Before")]
+    pub before: String,
+    #[tabled(rename = "After")]
+    pub after: String,
+}
+
+impl Baseline {
+    pub const VERSION: u32 = 1;
+
+    pub fn from_results(results: &[ResultTuple]) -> Self {
+        let files = results
+            .iter()
+            .map(|(_, rel_path, result)| {
+                (
+                    rel_path.clone(),
+                    FileBaseline {
+                        stats: Stats::from_result(result),
+                        functions: result
+                            .functions
+                            .iter()
+                            .map(|(name, function)| {
+                                (name.clone(), function.executed)
+                            })
+                            .collect(),
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            version: Self::VERSION,
+            total: results.iter().map(|(_, _, result)| result).collect(),
+            files,
+        }
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).with_context(|| {
+            format!("Could not read baseline {}", path.display())
+        })?;
+        let baseline: Self = serde_json::from_str(&contents).with_context(
+            || format!("Could not parse baseline {}", path.display()),
+        )?;
+
+        if baseline.version != Self::VERSION {
+            bail!(
+                "Unsupported baseline version {} in {} (expected {})",
+                baseline.version,
+                path.display(),
+                Self::VERSION
+            );
+        }
+
+        Ok(baseline)
+    }
+
+    pub fn write(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> eyre::Result<()> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self)?).with_context(
+            || format!("Could not write baseline {}", path.display()),
+        )
+    }
+
+    /// Files whose coverage dropped and functions which are no longer executed
+    ///
+    /// Files and functions which were removed since the baseline are not regressions
+    pub fn regressions(
+        &self,
+        current: &Self,
+    ) -> Vec<Regression> {
+        let mut regressions = stats_regressions("(total)", &self.total, &current.total);
+
+        for (rel_path, before) in &self.files {
+            let Some(after) = current.files.get(rel_path) else {
+                continue;
+            };
+            let subject = rel_path.display().to_string();
+
+            regressions.extend(stats_regressions(&subject, &before.stats, &after.stats));
+
+            regressions.extend(
+                before
+                    .functions
+                    .iter()
+                    .filter(|(name, executed)| {
+                        **executed && after.functions.get(*name) == Some(&false)
+                    })
+                    .map(|(name, _)| Regression {
+                        subject: format!("{} `{}`", subject, name),
+                        metric: "function",
+                        before: "executed".to_string(),
+                        after: "not executed".to_string(),
+                    }),
+            );
+        }
+
+        regressions
+    }
+}
+
+fn stats_regressions(
+    subject: &str,
+    before: &Stats,
+    after: &Stats,
+) -> Vec<Regression> {
+    [
+        ("lines", before.lines, after.lines),
+        ("functions", before.functions, after.functions),
+        ("branches", before.branches, after.branches),
+    ]
+    .into_iter()
+    .filter_map(|(metric, before, after): (_, Counter, Counter)| {
+        let (before_percent, after_percent) = (before.percent()?, after.percent()?);
+
+        // NOTE: a tolerance, so that float rounding isn't reported
+        (after_percent + 1e-9 < before_percent).then(|| Regression {
+            subject: subject.to_string(),
+            metric,
+            before: format!(
+                "{:.2}% ({}/{})",
+                before_percent, before.covered, before.total
+            ),
+            after: format!(
+                "{:.2}% ({}/{})",
+                after_percent, after.covered, after.total
+            ),
+        })
+    })
+    .collect()
+}
+
+pub fn table(regressions: &[Regression]) -> Table {
+    Table::new(regressions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_dropped_coverage_and_unexecuted_functions() {
+        let file = |covered, executed| FileBaseline {
+            stats: Stats {
+                lines: Counter::new(covered, 10),
+                ..Default::default()
+            },
+            functions: [
+                ("initialize".to_string(), true),
+                ("increment".to_string(), executed),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let baseline = |covered, executed| Baseline {
+            version: Baseline::VERSION,
+            total: Stats {
+                lines: Counter::new(covered, 10),
+                ..Default::default()
+            },
+            files: [("src/lib.rs".into(), file(covered, executed))]
+                .into_iter()
+                .collect(),
+        };
+
+        let before = baseline(8, true);
+
+        assert!(before.regressions(&baseline(9, true)).is_empty());
+
+        let regressions = before.regressions(&baseline(7, false));
+        let subjects = regressions
+            .iter()
+            .map(|r| (r.subject.as_str(), r.metric))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            subjects,
+            vec![
+                ("(total)", "lines"),
+                ("src/lib.rs", "lines"),
+                ("src/lib.rs `increment`", "function"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+
+use super::{
+    workspace::{Package, Workspace},
+    ContractStyle,
+};
+
+/// The contract style of each workspace member, used to pick the tree-sitter query which finds
+/// a file's contract functions
+#[derive(Debug, Clone, Default)]
+pub struct ContractStyles {
+    /// (package directory, style), the most nested package wins for a given path
+    by_dir: Vec<(PathBuf, ContractStyle)>,
+    /// For paths outside of every workspace member
+    fallback: ContractStyle,
+}
+
+impl ContractStyles {
+    /// Every file gets the same style
+    pub fn uniform(style: ContractStyle) -> Self {
+        Self {
+            by_dir: vec![],
+            fallback: style,
+        }
+    }
+
+    /// Detect the style of each workspace member, see `detect`
+    pub fn detect_all(workspace: &Workspace) -> eyre::Result<Self> {
+        let anchor_programs = anchor_programs(&workspace.root)?;
+
+        let mut by_dir = workspace
+            .members
+            .iter()
+            .map(|package| {
+                (
+                    package.dir().to_path_buf(),
+                    detect(package, &anchor_programs),
+                )
+            })
+            .collect::<Vec<_>>();
+        // NOTE: deepest directories first, so that nested packages take precedence
+        by_dir.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+
+        Ok(Self {
+            by_dir,
+            fallback: ContractStyle::Native,
+        })
+    }
+
+    pub fn for_path(
+        &self,
+        path: &Path,
+    ) -> ContractStyle {
+        self.by_dir
+            .iter()
+            .find(|(dir, _)| path.starts_with(dir))
+            .map_or(self.fallback, |(_, style)| *style)
+    }
+}
+
+/// A package is an Anchor program if it depends on `anchor-lang` or is listed as one of the
+/// `[programs.<cluster>]` in the `Anchor.toml`
+///
+/// Everything else (plain `solana-program` programs, but also helper crates) is treated as
+/// native, so that all of its functions are considered
+pub fn detect(
+    package: &Package,
+    anchor_programs: &BTreeSet<String>,
+) -> ContractStyle {
+    // NOTE: `Anchor.toml` uses the library names
+    let lib_name = package.name.replace('-', "_");
+
+    if package.depends_on("anchor-lang") || anchor_programs.contains(&lib_name) {
+        ContractStyle::Anchor
+    } else {
+        ContractStyle::Native
+    }
+}
+
+/// Names of the programs in the `Anchor.toml` at the workspace root (across all clusters)
+fn anchor_programs(root: &Path) -> eyre::Result<BTreeSet<String>> {
+    let path = root.join("Anchor.toml");
+    if !path.exists() {
+        return Ok(BTreeSet::new());
+    }
+
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+    let anchor_toml: toml::Table = toml::from_str(&contents)
+        .with_context(|| format!("Could not parse {}", path.display()))?;
+
+    Ok(anchor_toml
+        .get("programs")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|clusters| clusters.values())
+        .filter_map(toml::Value::as_table)
+        .flat_map(|programs| programs.keys().cloned())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::coverage::workspace::Dependency;
+
+    #[test]
+    fn detects_style_per_package() {
+        let package = |name: &str, dependencies: &[(&str, Option<&str>)]| Package {
+            name: name.to_string(),
+            manifest_path: PathBuf::from(format!("/ws/programs/{}/Cargo.toml", name)),
+            dependencies: dependencies
+                .iter()
+                .map(|(name, kind)| Dependency {
+                    name: name.to_string(),
+                    kind: kind.map(str::to_string),
+                })
+                .collect(),
+        };
+        let anchor_programs = ["listed_counter".to_string()].into_iter().collect();
+
+        let styles = [
+            package("counter", &[("anchor-lang", None)]),
+            package("listed-counter", &[]),
+            package("vault", &[("solana-program", None)]),
+            package("test-utils", &[("anchor-lang", Some("dev"))]),
+        ]
+        .iter()
+        .map(|package| detect(package, &anchor_programs))
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            styles,
+            vec![
+                ContractStyle::Anchor,
+                ContractStyle::Anchor,
+                ContractStyle::Native,
+                ContractStyle::Native,
+            ]
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use eyre::bail;
+use grcov::ResultTuple;
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::stats::Counter;
+
+/// Coverage of the lines added or modified since a base revision
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiffCoverage {
+    pub base: String,
+    pub lines: Counter,
+    pub files: Vec<FileDiffCoverage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FileDiffCoverage {
+    pub rel_path: PathBuf,
+    /// (line, hits) of each changed line which is instrumented
+    pub lines: Vec<(u32, u64)>,
+}
+
+impl FileDiffCoverage {
+    pub fn uncovered(&self) -> impl Iterator<Item = u32> + '_ {
+        self.lines
+            .iter()
+            .filter(|(_, hits)| *hits == 0)
+            .map(|(line, _)| *line)
+    }
+}
+
+impl DiffCoverage {
+    pub const HTML_FILE_NAME: &'static str = "diff.html";
+
+    /// Only the (program) files in `results` are considered, changed lines which aren't
+    /// instrumented (comments, excluded lines, ...) are skipped
+    pub fn new(
+        base: &str,
+        results: &[ResultTuple],
+        changed_lines: &BTreeMap<PathBuf, BTreeSet<u32>>,
+    ) -> Self {
+        let mut diff_coverage = Self {
+            base: base.to_string(),
+            ..Default::default()
+        };
+
+        for (path, rel_path, result) in results {
+            // NOTE: `changed_lines` is keyed by canonical paths
+            let Some(changed) = fs::canonicalize(path)
+                .ok()
+                .and_then(|path| changed_lines.get(&path))
+            else {
+                continue;
+            };
+
+            let lines = changed
+                .iter()
+                .filter_map(|line| Some((*line, *result.lines.get(line)?)))
+                .collect::<Vec<_>>();
+            if lines.is_empty() {
+                continue;
+            }
+
+            diff_coverage.lines += Counter::new(
+                lines.iter().filter(|(_, hits)| *hits > 0).count(),
+                lines.len(),
+            );
+            diff_coverage.files.push(FileDiffCoverage {
+                rel_path: rel_path.clone(),
+                lines,
+            });
+        }
+
+        diff_coverage
+    }
+
+    pub fn print(&self) {
+        match self.lines.percent() {
+            Some(percent) => eprintln!(
+                "Diff coverage (against `{}`): {:.2}% ({}/{} changed lines)",
+                self.base, percent, self.lines.covered, self.lines.total
+            ),
+            None => eprintln!(
+                "Diff coverage (against `{}`): no changed program lines",
+                self.base
+            ),
+        }
+
+        for file in &self.files {
+            let uncovered = display_ranges(file.uncovered());
+            if !uncovered.is_empty() {
+                eprintln!(
+                    "  {}: uncovered {}",
+                    file.rel_path.display(),
+                    uncovered
+                );
+            }
+        }
+    }
+
+    /// For the diff page of the HTML report, with the changed lines of each file
+    pub fn html_context(
+        &self,
+        source_root: &Path,
+    ) -> tera::Context {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            number: u32,
+            hits: u64,
+            source: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct File<'a> {
+            path: String,
+            lines: Vec<Line<'a>>,
+        }
+
+        let sources = self
+            .files
+            .iter()
+            .map(|file| {
+                fs::read_to_string(source_root.join(&file.rel_path))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let files = self
+            .files
+            .iter()
+            .zip(&sources)
+            .map(|(file, source)| {
+                let source_lines = source.lines().collect::<Vec<_>>();
+
+                File {
+                    path: file.rel_path.display().to_string(),
+                    lines: file
+                        .lines
+                        .iter()
+                        .map(|(number, hits)| Line {
+                            number: *number,
+                            hits: *hits,
+                            // NOTE: `CovResult`'s `lines` are 1-indexed
+                            source: source_lines
+                                .get(*number as usize - 1)
+                                .copied()
+                                .unwrap_or_default(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut context = tera::Context::new();
+        context.insert("base", &self.base);
+        context.insert("percent", &self.lines.percent());
+        context.insert("lines", &self.lines);
+        context.insert("files", &files);
+        context
+    }
+}
+
+/// Lines added or modified since `base` (including uncommitted changes and untracked files),
+/// keyed by canonical path
+pub fn changed_lines(
+    dir: &Path,
+    base: &str,
+) -> eyre::Result<BTreeMap<PathBuf, BTreeSet<u32>>> {
+    let git = |args: &[&str]| -> eyre::Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?
+            .wait_with_output()?;
+        if !output.status.success() {
+            eprintln!("git stderr:");
+            eprintln!("{}", std::str::from_utf8(&output.stderr)?);
+            bail!("`git {}` failed", args.join(" "));
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let toplevel = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let diff = git(&[
+        "diff",
+        "--unified=0",
+        "--no-color",
+        "--no-ext-diff",
+        // NOTE: `parse_diff` expects the default prefixes, regardless of `diff.noprefix` or
+        //       `diff.mnemonicPrefix`
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+        base,
+        "--",
+    ])?;
+
+    let mut changed = parse_diff(&diff);
+
+    // NOTE: untracked (but not ignored) files aren't in the diff, all of their lines are new (only
+    //       Rust sources can have coverage)
+    let untracked = git(&["ls-files", "--others", "--exclude-standard", "--full-name", "-z"])?;
+    for rel_path in untracked.split('\0').filter(|rel_path| rel_path.ends_with(".rs")) {
+        let Ok(source) = fs::read_to_string(toplevel.join(rel_path)) else {
+            continue;
+        };
+        changed
+            .entry(PathBuf::from(rel_path))
+            .or_default()
+            .extend(1..=source.lines().count() as u32);
+    }
+
+    // NOTE: changed files which no longer exist can't have coverage
+    Ok(changed
+        .into_iter()
+        .filter_map(|(rel_path, lines)| {
+            Some((fs::canonicalize(toplevel.join(rel_path)).ok()?, lines))
+        })
+        .collect())
+}
+
+/// Parse the added lines out of a `git diff --unified=0`
+pub fn parse_diff(diff: &str) -> BTreeMap<PathBuf, BTreeSet<u32>> {
+    let mut changed: BTreeMap<PathBuf, BTreeSet<u32>> = BTreeMap::new();
+    let mut current: Option<PathBuf> = None;
+
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            // NOTE: paths with spaces are followed by a tab, the ones with special (or non-ASCII)
+            //       characters are quoted
+            let path = path.strip_suffix('\t').unwrap_or(path);
+            let path = match path.strip_prefix('"') {
+                Some(quoted) => unquote(quoted.strip_suffix('"').unwrap_or(quoted)),
+                None => Some(path.to_string()),
+            };
+            // NOTE: deleted files are `+++ /dev/null`
+            current = path
+                .as_deref()
+                .and_then(|path| path.strip_prefix("b/"))
+                .map(PathBuf::from);
+        } else if let Some(hunk) = line.strip_prefix("@@ ") {
+            let Some(path) = current.as_ref() else {
+                continue;
+            };
+
+            // NOTE: `@@ -start[,count] +start[,count] @@`
+            let Some(added) = hunk
+                .split_whitespace()
+                .find_map(|range| range.strip_prefix('+'))
+            else {
+                continue;
+            };
+            let (start, count) = match added.split_once(',') {
+                Some((start, count)) => (start.parse::<u32>(), count.parse::<u32>()),
+                None => (added.parse::<u32>(), Ok(1)),
+            };
+            let (Ok(start), Ok(count)) = (start, count) else {
+                continue;
+            };
+
+            changed
+                .entry(path.clone())
+                .or_default()
+                .extend(start..start + count);
+        }
+    }
+
+    changed
+}
+
+/// Undo git's C-style quoting of a path (without the quotes), e.g. `b/\303\274.rs` -> `b/ü.rs`
+fn unquote(quoted: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        let escaped = chars.next()?;
+        bytes.push(match escaped {
+            'a' => 0x07,
+            'b' => 0x08,
+            't' => b'\t',
+            'n' => b'\n',
+            'v' => 0x0b,
+            'f' => 0x0c,
+            'r' => b'\r',
+            // NOTE: a byte in octal, e.g. `\303`
+            '0'..='7' => {
+                let octal = std::iter::once(escaped)
+                    .chain(chars.by_ref().take(2))
+                    .collect::<String>();
+                u8::from_str_radix(&octal, 8).ok()?
+            }
+            c => c as u8,
+        });
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// `1, 2, 3, 5` -> `1-3, 5`
+fn display_ranges(lines: impl Iterator<Item = u32>) -> String {
+    lines
+        .map(|line| (line, line))
+        .coalesce(|(start, end), (next_start, next_end)| {
+            if end + 1 == next_start {
+                Ok((start, next_end))
+            } else {
+                Err(((start, end), (next_start, next_end)))
+            }
+        })
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .join(", ")
+}
+
+#[rustfmt::skip]
+pub const HTML_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}diff coverage{% endblock title %}
+{% block content %}
+  <h1>Diff coverage against <code>{{ base }}</code></h1>
+  {% if lines.total > 0 %}
+  <p>{{ percent | round(precision=2) }}% ({{ lines.covered }}/{{ lines.total }} changed lines)</p>
+  {% else %}
+  <p>No changed program lines</p>
+  {% endif %}
+  {% for file in files %}
+  <h2><a href="{{ file.path | source_page }}">{{ file.path }}</a></h2>
+  <table>
+    <tr><th>Line</th><th>Hits</th><th>Source</th></tr>
+    {% for line in file.lines %}
+    <tr class="{% if line.hits > 0 %}covered{% else %}uncovered{% endif %}">
+      <td class="number"><a href="{{ file.path | source_page }}#L{{ line.number }}">{{ line.number }}</a></td>
+      <td class="number">{{ line.hits }}</td>
+      <td class="source">{{ line.source }}</td>
+    </tr>
+    {% endfor %}
+  </table>
+  {% endfor %}
+{% endblock content %}
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_added_lines() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3 +3,2 @@ fn process()
+-    x + 1
++    x + 2
++    // comment
+@@ -10,2 +11,0 @@
+-removed
+-removed
+@@ -20 +20 @@
+-a
++b
+diff --git a/src/old.rs b/src/old.rs
+--- a/src/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+diff --git a/src/my file.rs b/src/my file.rs
+--- a/src/my file.rs\t
++++ b/src/my file.rs\t
+@@ -1,0 +2 @@
++b
+diff --git \"a/src/\\303\\274.rs\" \"b/src/\\303\\274.rs\"
+--- \"a/src/\\303\\274.rs\"
++++ \"b/src/\\303\\274.rs\"
+@@ -1,0 +2 @@
++b
+";
+
+        let changed = parse_diff(diff);
+
+        assert_eq!(changed.len(), 3);
+        assert_eq!(
+            changed[Path::new("src/lib.rs")],
+            [3, 4, 20].into_iter().collect()
+        );
+        assert_eq!(changed[Path::new("src/my file.rs")], [2].into_iter().collect());
+        assert_eq!(changed[Path::new("src/ü.rs")], [2].into_iter().collect());
+    }
+
+    #[test]
+    fn finds_changed_and_untracked_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=zest", "-c", "user.email=zest@localhost"])
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init"]);
+        fs::write(dir.path().join("my file.rs"), "a\n").unwrap();
+        fs::write(dir.path().join("ü.rs"), "a\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-m", "initial"]);
+        fs::write(dir.path().join("my file.rs"), "a\nb\n").unwrap();
+        fs::write(dir.path().join("ü.rs"), "a\nb\n").unwrap();
+        fs::write(dir.path().join("new.rs"), "a\nb\nc\n").unwrap();
+
+        let changed = changed_lines(dir.path(), "HEAD").unwrap();
+
+        let root = fs::canonicalize(dir.path()).unwrap();
+        assert_eq!(
+            changed,
+            BTreeMap::from([
+                (root.join("my file.rs"), BTreeSet::from([2])),
+                (root.join("new.rs"), BTreeSet::from([1, 2, 3])),
+                (root.join("ü.rs"), BTreeSet::from([2])),
+            ])
+        );
+    }
+
+    #[test]
+    fn displays_ranges() {
+        assert_eq!(display_ranges([1, 2, 3, 5, 7, 8].into_iter()), "1-3, 5, 7-8");
+    }
+}
@@ -0,0 +1,224 @@
+use std::path::PathBuf;
+
+use eyre::Context;
+use grcov::ResultTuple;
+use itertools::Itertools;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+
+use crate::parsing::{extract_enums, extract_instruction_dispatch, ParsedFile};
+
+use super::{
+    contract_style::ContractStyles, instructions::program_name, stats::Counter,
+    workspace::Workspace, ContractStyle,
+};
+
+/// Whether a single variant of a native program's instruction enum was dispatched by the tests
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantCoverage {
+    pub program: String,
+    pub enum_name: String,
+    pub variant: String,
+    /// Location of the `match` arm, or of the variant's definition if it has no arm of its own
+    pub rel_path: PathBuf,
+    pub line: u32,
+    pub end_line: u32,
+    /// Line hits of the `match` arm, `None` if the variant is only handled by a wildcard arm
+    pub hits: Option<u64>,
+    /// Names of the functions called by the variant's arm(s)
+    pub calls: Vec<String>,
+}
+
+#[derive(Tabled)]
+struct VariantRow {
+    #[tabled(rename = "Program")]
+    program: String,
+    #[tabled(rename = "Instruction")]
+    instruction: String,
+    #[tabled(rename = "Location")]
+    location: String,
+    #[tabled(rename = "Dispatched")]
+    dispatched: String,
+}
+
+/// Instruction dispatch coverage of the native programs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DispatchCoverage {
+    pub variants: Vec<VariantCoverage>,
+    pub dispatched: Counter,
+}
+
+impl DispatchCoverage {
+    pub const HTML_FILE_NAME: &'static str = "dispatch.html";
+
+    /// Find the instruction `match` in the processors of the (native) files of `results` and
+    /// check which of its arms were executed
+    ///
+    /// The variants are taken from the instruction enum's definition (if it is in one of the
+    /// program's files), so that variants without an arm of their own are reported too
+    pub fn new(
+        results: &[ResultTuple],
+        workspace: &Workspace,
+        contract_styles: &ContractStyles,
+    ) -> eyre::Result<Self> {
+        // NOTE: each (native) file is parsed once, its dispatch and enums are then looked up
+        //       by program
+        let mut files = vec![];
+        for (path, rel_path, result) in results {
+            if contract_styles.for_path(path) != ContractStyle::Native {
+                continue;
+            }
+            let parse_error = || format!("Could not parse {}", path.display());
+            let file = ParsedFile::read(path).with_context(parse_error)?;
+            let dispatch = extract_instruction_dispatch(&file).with_context(parse_error)?;
+            let enums = extract_enums(&file).with_context(parse_error)?;
+            files.push((
+                program_name(workspace, path, rel_path),
+                rel_path,
+                result,
+                dispatch,
+                enums,
+            ));
+        }
+
+        let mut variants = vec![];
+
+        for (program, rel_path, result, dispatch, _) in &files {
+            let Some(dispatch) = dispatch else {
+                continue;
+            };
+            let enum_name = &dispatch.enum_name;
+
+            // NOTE: the enum is looked for in the same program's files
+            let mut definition = files
+                .iter()
+                .filter(|(other_program, ..)| other_program == program)
+                .flat_map(|(_, other_rel_path, _, _, enums)| {
+                    enums.get(enum_name).into_iter().flatten().map(|(variant, position)| {
+                        (
+                            variant.clone(),
+                            (*other_rel_path).clone(),
+                            position.row as u32 + 1,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            if definition.is_empty() {
+                definition = dispatch
+                    .arms
+                    .iter()
+                    .map(|arm| (arm.variant.clone(), (*rel_path).clone(), 0))
+                    .unique_by(|(variant, _, _)| variant.clone())
+                    .collect();
+            }
+
+            for (variant, definition_rel_path, definition_line) in definition {
+                // NOTE: a variant can have multiple arms (e.g. with `if` guards)
+                let arms = dispatch
+                    .arms
+                    .iter()
+                    .filter(|arm| arm.variant == variant)
+                    .collect::<Vec<_>>();
+
+                // NOTE: `Point`'s `row` is 0-indexed while `CovResult`'s `lines` are 1-indexed
+                let hits = (!arms.is_empty()).then(|| {
+                    arms.iter()
+                        .flat_map(|arm| {
+                            result
+                                .lines
+                                .range(arm.span.0.row as u32 + 1..=arm.span.1.row as u32 + 1)
+                                .map(|(_, hits)| *hits)
+                        })
+                        .max()
+                        .unwrap_or_default()
+                });
+                let (rel_path, line, end_line) = match (arms.first(), arms.last()) {
+                    (Some(first), Some(last)) => (
+                        (*rel_path).clone(),
+                        first.span.0.row as u32 + 1,
+                        last.span.1.row as u32 + 1,
+                    ),
+                    _ => (definition_rel_path, definition_line, definition_line),
+                };
+
+                variants.push(VariantCoverage {
+                    program: program.clone(),
+                    enum_name: enum_name.clone(),
+                    variant,
+                    rel_path,
+                    line,
+                    end_line,
+                    hits,
+                    calls: arms
+                        .iter()
+                        .flat_map(|arm| arm.calls.iter().cloned())
+                        .unique()
+                        .collect(),
+                });
+            }
+        }
+
+        let dispatched = Counter::new(
+            variants
+                .iter()
+                .filter(|variant| variant.hits.map_or(false, |hits| hits > 0))
+                .count(),
+            variants.len(),
+        );
+
+        Ok(Self {
+            variants,
+            dispatched,
+        })
+    }
+
+    pub fn table(&self) -> Table {
+        Table::new(self.variants.iter().map(|variant| VariantRow {
+            program: variant.program.clone(),
+            instruction: format!("{}::{}", variant.enum_name, variant.variant),
+            location: format!("{}:{}", variant.rel_path.display(), variant.line),
+            dispatched: match variant.hits {
+                Some(0) => "no".to_string(),
+                Some(hits) => format!("yes ({} line hits)", hits),
+                None => "no arm (wildcard)".to_string(),
+            },
+        }))
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "Instruction dispatch coverage: {}/{} instruction(s) dispatched by the tests",
+            self.dispatched.covered, self.dispatched.total
+        );
+        eprintln!("{}", self.table());
+    }
+
+    /// For the dispatch page of the HTML report
+    pub fn html_context(&self) -> tera::Context {
+        let mut context = tera::Context::new();
+        context.insert("variants", &self.variants);
+        context.insert("dispatched", &self.dispatched);
+        context
+    }
+}
+
+#[rustfmt::skip]
+pub const HTML_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}instruction dispatch coverage{% endblock title %}
+{% block content %}
+  <h1>Instruction dispatch coverage</h1>
+  <p>{{ dispatched.covered }}/{{ dispatched.total }} instruction(s) dispatched by the tests</p>
+  <table>
+    <tr><th>Program</th><th>Instruction</th><th>Location</th><th>Line hits</th></tr>
+    {% for variant in variants %}
+    <tr class="{% if not variant.hits %}uncovered{% else %}covered{% endif %}">
+      <td><a href="{{ variant.program | program_page }}">{{ variant.program }}</a></td>
+      <td><code>{{ variant.enum_name }}::{{ variant.variant }}</code></td>
+      <td><a href="{{ variant.rel_path | source_page }}#L{{ variant.line }}">{{ variant.rel_path }}:{{ variant.line }}</a></td>
+      <td class="number">{% if variant.hits is number %}{{ variant.hits }}{% else %}no arm (wildcard){% endif %}</td>
+    </tr>
+    {% endfor %}
+  </table>
+{% endblock content %}
+"##;
@@ -0,0 +1,234 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use eyre::Context;
+use grcov::ResultTuple;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+
+use crate::parsing::{extract_custom_errors, extract_test_items, ParsedFile};
+
+use super::{
+    instructions::{program_ids, program_name},
+    stats::Counter,
+    workspace::Workspace,
+};
+
+lazy_static! {
+    static ref CUSTOM_PROGRAM_ERROR: Regex = Regex::new(
+        r"(?:Program (\S+) failed: )?custom program error: 0x([0-9a-fA-F]+)"
+    )
+    .unwrap();
+}
+
+/// How many times each custom error code was returned, keyed by (program id, code)
+///
+/// The program id is `None` if the error was not logged as a program failure (e.g. only as
+/// part of a `TransactionError` returned to the test)
+pub type ErrorLog = BTreeMap<(Option<String>, u32), u64>;
+
+/// Parse the `custom program error: 0x..` lines in the output of the tests
+pub fn parse_custom_errors(log: &str) -> ErrorLog {
+    let mut errors = ErrorLog::new();
+
+    for captures in CUSTOM_PROGRAM_ERROR.captures_iter(log) {
+        let Ok(code) = u32::from_str_radix(&captures[2], 16) else {
+            continue;
+        };
+        let program_id = captures.get(1).map(|id| id.as_str().to_string());
+        *errors.entry((program_id, code)).or_default() += 1;
+    }
+
+    errors
+}
+
+/// Whether a single custom error of a program was triggered by the tests
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorCoverage {
+    pub program: String,
+    pub name: String,
+    pub code: u32,
+    pub rel_path: PathBuf,
+    pub line: u32,
+    pub triggered: u64,
+}
+
+#[derive(Tabled)]
+struct ErrorRow {
+    #[tabled(rename = "Program")]
+    program: String,
+    #[tabled(rename = "Error")]
+    name: String,
+    #[tabled(rename = "Code")]
+    code: String,
+    #[tabled(rename = "Location")]
+    location: String,
+    #[tabled(rename = "Triggered")]
+    triggered: u64,
+}
+
+/// Custom error coverage of the programs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CustomErrorCoverage {
+    pub errors: Vec<ErrorCoverage>,
+    pub triggered: Counter,
+}
+
+impl CustomErrorCoverage {
+    pub const HTML_FILE_NAME: &'static str = "errors.html";
+
+    /// Find the custom errors in the files of `results` and match them with the logged ones by
+    /// their code (and their program's `declare_id!`, if known)
+    pub fn new(
+        results: &[ResultTuple],
+        workspace: &Workspace,
+        error_log: &ErrorLog,
+    ) -> eyre::Result<Self> {
+        let program_ids = program_ids(results, workspace)?;
+        // NOTE: the logged counts of each code, so that the log isn't scanned once per error
+        let mut logged_codes = BTreeMap::<u32, Vec<(Option<&String>, u64)>>::new();
+        for ((program_id, code), count) in error_log {
+            logged_codes
+                .entry(*code)
+                .or_default()
+                .push((program_id.as_ref(), *count));
+        }
+
+        let mut errors = vec![];
+
+        for (path, rel_path, _) in results {
+            let parse_error = || format!("Could not parse {}", path.display());
+            let file = ParsedFile::read(path).with_context(parse_error)?;
+            let custom_errors = extract_custom_errors(&file).with_context(parse_error)?;
+            if custom_errors.is_empty() {
+                continue;
+            }
+            // NOTE: e.g. the `ProgramError::Custom(n)`s expected by unit tests
+            let test_items = extract_test_items(&file).with_context(parse_error)?;
+
+            let program = program_name(workspace, path, rel_path);
+            let program_id = program_ids.get(&program);
+
+            for custom_error in custom_errors {
+                if test_items.iter().any(|(start, end)| {
+                    (start.row..=end.row).contains(&custom_error.position.row)
+                }) {
+                    continue;
+                }
+
+                let logged = |matches_id: &dyn Fn(Option<&String>) -> bool| -> u64 {
+                    logged_codes
+                        .get(&custom_error.code)
+                        .into_iter()
+                        .flatten()
+                        .filter(|(id, _)| matches_id(*id))
+                        .map(|(_, count)| count)
+                        .sum()
+                };
+                // NOTE: program failures are preferred, since the same error is usually
+                //       also logged again as part of the `TransactionError`
+                let failures = logged(&|id| {
+                    id.is_some() && (program_id.is_none() || id == program_id)
+                });
+                let triggered = if failures > 0 {
+                    failures
+                } else {
+                    logged(&|id| id.is_none())
+                };
+
+                errors.push(ErrorCoverage {
+                    program: program.clone(),
+                    name: custom_error.name,
+                    code: custom_error.code,
+                    rel_path: rel_path.clone(),
+                    // NOTE: `Point`'s `row` is 0-indexed
+                    line: custom_error.position.row as u32 + 1,
+                    triggered,
+                });
+            }
+        }
+
+        errors.sort_by(|a, b| {
+            (&a.program, &a.rel_path, a.line).cmp(&(&b.program, &b.rel_path, b.line))
+        });
+        let triggered = Counter::new(
+            errors.iter().filter(|error| error.triggered > 0).count(),
+            errors.len(),
+        );
+
+        Ok(Self { errors, triggered })
+    }
+
+    pub fn table(&self) -> Table {
+        Table::new(self.errors.iter().map(|error| ErrorRow {
+            program: error.program.clone(),
+            name: error.name.clone(),
+            code: format!("{} (0x{:x})", error.code, error.code),
+            location: format!("{}:{}", error.rel_path.display(), error.line),
+            triggered: error.triggered,
+        }))
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "Custom error coverage: {}/{} error(s) triggered by the tests",
+            self.triggered.covered, self.triggered.total
+        );
+        eprintln!("{}", self.table());
+    }
+
+    /// For the errors page of the HTML report
+    pub fn html_context(&self) -> tera::Context {
+        let mut context = tera::Context::new();
+        context.insert("errors", &self.errors);
+        context.insert("triggered", &self.triggered);
+        context
+    }
+}
+
+#[rustfmt::skip]
+pub const HTML_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}custom error coverage{% endblock title %}
+{% block content %}
+  <h1>Custom error coverage</h1>
+  <p>{{ triggered.covered }}/{{ triggered.total }} error(s) triggered by the tests</p>
+  <table>
+    <tr><th>Program</th><th>Error</th><th>Code</th><th>Location</th><th>Triggered</th></tr>
+    {% for error in errors %}
+    <tr class="{% if error.triggered == 0 %}uncovered{% else %}covered{% endif %}">
+      <td><a href="{{ error.program | program_page }}">{{ error.program }}</a></td>
+      <td><code>{{ error.name }}</code></td>
+      <td class="number">{{ error.code }}</td>
+      <td><a href="{{ error.rel_path | source_page }}#L{{ error.line }}">{{ error.rel_path }}:{{ error.line }}</a></td>
+      <td class="number">{{ error.triggered }}</td>
+    </tr>
+    {% endfor %}
+  </table>
+{% endblock content %}
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_custom_errors() {
+        let log = "\
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program Counter111 failed: custom program error: 0x1770
+thread 'tests::test_overflow' panicked at 'called `Result::unwrap()` on an `Err` value: TransactionError(InstructionError(0, Custom(6000)))'
+Error processing Instruction 0: custom program error: 0x7b
+";
+
+        let errors = parse_custom_errors(log);
+
+        assert_eq!(
+            errors.into_iter().collect::<Vec<_>>(),
+            vec![
+                ((None, 123), 1),
+                ((Some("Counter111".to_string()), 6000), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use eyre::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tree_sitter::Query;
+
+use crate::parsing::LANGUAGE;
+
+/// Lines which are never contract code
+pub const BUILTIN_LINES: &str = r#"
+    ^\s*\#\[(program|account)\]$       # Matches #[program] or #[account]
+    |
+    ^\s*\#\[(tokio::)?test\]$          # Matches #[test] or #[tokio::test]
+    |
+    ^\s*\#\[derive\(\s*[^\)]+\s*\)\]$  # Matches #[derive(Trait, ...)]
+    |
+    ^\s*declare_id!\(\s*.*\s*\);$      # Matches declare_id!(...)
+    |
+    ^\s*declare_program!\(\s*.*\s*\);$ # Matches declare_id!(...)
+    # |
+    # ^\s*$                              # Matches "empty" lines
+    # |
+    # ^\s*[\(\)\[\]\{\}]*\s*$            # Matches lines with only brackets
+"#;
+
+/// User-defined exclusions from the coverage (config file only, the `[exclusions]` table)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Exclusions {
+    /// Regexes of lines to exclude (in addition to [BUILTIN_LINES])
+    pub lines: Vec<String>,
+    /// Excluded sections, e.g. between `// zest:off` and `// zest:on`
+    pub markers: Vec<Marker>,
+    /// Tree-sitter queries, the nodes captured by which are excluded (captures starting with
+    /// `_` are left alone, so that they can be used in predicates)
+    pub queries: Vec<String>,
+}
+
+/// Regexes of the first and last line of an excluded section (both lines are excluded too)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Marker {
+    pub start: String,
+    pub stop: String,
+}
+
+impl Exclusions {
+    /// The builtin and the user-defined line exclusions, as a single regex
+    pub fn line_regex(&self) -> eyre::Result<Regex> {
+        // NOTE: the user-defined ones are not in verbose mode, whitespace in them is literal
+        let regex = std::iter::once(format!("(?x:{})", BUILTIN_LINES))
+            .chain(self.lines.iter().map(|line| format!("(?:{})", line)))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        Regex::new(&regex).wrap_err("Invalid line exclusion regex")
+    }
+
+    /// The start and stop regexes of all of the markers (`None` if there are no markers)
+    ///
+    /// NOTE: sections can't be nested, any of the stop markers ends the current section
+    pub fn marker_regexes(&self) -> eyre::Result<(Option<Regex>, Option<Regex>)> {
+        if self.markers.is_empty() {
+            return Ok((None, None));
+        }
+
+        let join = |regexes: Vec<&String>| {
+            let regex = regexes
+                .into_iter()
+                .map(|regex| format!("(?:{})", regex))
+                .collect::<Vec<_>>()
+                .join("|");
+            Regex::new(&regex).wrap_err("Invalid exclusion marker regex")
+        };
+
+        Ok((
+            Some(join(self.markers.iter().map(|marker| &marker.start).collect())?),
+            Some(join(self.markers.iter().map(|marker| &marker.stop).collect())?),
+        ))
+    }
+
+    /// Parse the tree-sitter queries
+    pub fn compile_queries(queries: &[String]) -> eyre::Result<Vec<Query>> {
+        queries
+            .iter()
+            .map(|query| {
+                Query::new(&LANGUAGE, query)
+                    .with_context(|| format!("Invalid exclusion query `{}`", query))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_line_exclusions() {
+        let exclusions = Exclusions {
+            lines: vec![r"^\s*msg!\(".to_string(), "// not covered".to_string()],
+            markers: vec![
+                Marker {
+                    start: "zest:off".to_string(),
+                    stop: "zest:on".to_string(),
+                },
+                Marker {
+                    start: "coverage:off".to_string(),
+                    stop: "coverage:on".to_string(),
+                },
+            ],
+            queries: vec![],
+        };
+
+        let line_regex = exclusions.line_regex().unwrap();
+        assert!(line_regex.is_match("    declare_id!(\"Counter111\");"));
+        assert!(line_regex.is_match("    msg!(\"Counter: {}\", counter.count);"));
+        assert!(line_regex.is_match("    unreachable!() // not covered"));
+        assert!(!line_regex.is_match("    counter.count += 1;"));
+
+        let (start, stop) = exclusions.marker_regexes().unwrap();
+        assert!(start.unwrap().is_match("// coverage:off"));
+        assert!(stop.unwrap().is_match("// zest:on"));
+        assert!(Exclusions::default().marker_regexes().unwrap().0.is_none());
+    }
+}
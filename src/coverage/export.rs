@@ -0,0 +1,441 @@
+//! `zest`'s own JSON export, organized by program -> instruction -> function
+//!
+//! The schema is versioned by `schema_version`, which is bumped on every breaking change
+//! (removed/renamed fields or changed meanings). New fields may be added without a bump
+//!
+//! Version 1:
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "zest_version": "0.1.0",
+//!   "branch": false,                      // whether branch coverage was recorded
+//!   "totals": Stats,
+//!   "programs": [{
+//!     "name": "counter",                  // workspace member (or file outside of all members)
+//!     "contract_style": "anchor",         // or "native"
+//!     "program_id": "Counter111...",      // from `declare_id!`, null if not found
+//!     "totals": Stats,
+//!     "instructions": [{
+//!       "name": "Increment",              // as logged by Anchor / the instruction enum variant
+//!       "kind": "handler",                // `#[program]` handler, or "dispatch_arm" (native)
+//!       "file": "programs/counter/src/lib.rs",
+//!       "line": 15,
+//!       "executed": true,
+//!       "invocations": { "total": 2, "succeeded": 1, "failed": 1 },  // handlers only, else null
+//!       "functions": [Function]           // the handler's own function / the ones its arm calls
+//!     }],
+//!     "functions": [Function],            // the other contract functions of the program
+//!     "errors": [{ "name": "CounterError::Overflow", "code": 6000, "file": "...", "line": 33, "triggered": 1 }],
+//!     "files": [{ "path": "programs/counter/src/lib.rs", "totals": Stats }]
+//!   }]
+//! }
+//!
+//! Stats = {
+//!   "lines": { "covered": 16, "total": 30 },
+//!   "functions": { "covered": 2, "total": 3 },
+//!   "branches": { "covered": 0, "total": 0 }
+//! }
+//!
+//! Function = {
+//!   "name": "counter::increment",         // module/impl-qualified, `#2`, `#3`, ... if it collides within its file
+//!   "file": "programs/counter/src/lib.rs",
+//!   "start_line": 15,                     // whole span (tree-sitter), 1-indexed, inclusive
+//!   "end_line": 24,
+//!   "executed": true,
+//!   "executions": 6,                      // summed function records, null unless `--function-coverage records`
+//!   "lines": { "covered": 10, "total": 10 },
+//!   "branches": { "covered": 0, "total": 0 },
+//!   "line_hits": { "15": 6, "17": 6 },    // per (instrumented) line
+//!   "branch_hits": { "18": [true, false] } // per line, whether each branch was taken
+//! }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use grcov::ResultTuple;
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::{
+    contract_style::ContractStyles,
+    dispatch::DispatchCoverage,
+    errors::CustomErrorCoverage,
+    functions::FunctionSpanCoverage,
+    instructions::{program_ids, program_name, InstructionCoverage, Invocations},
+    stats::{Counter, Stats},
+    workspace::Workspace,
+    ContractStyle,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+pub const FILE_NAME: &str = "zest.json";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Export {
+    pub schema_version: u32,
+    pub zest_version: &'static str,
+    pub branch: bool,
+    pub totals: Stats,
+    pub programs: Vec<ProgramExport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramExport {
+    pub name: String,
+    pub contract_style: ContractStyle,
+    pub program_id: Option<String>,
+    pub totals: Stats,
+    pub instructions: Vec<InstructionExport>,
+    pub functions: Vec<FunctionExport>,
+    pub errors: Vec<ErrorExport>,
+    pub files: Vec<FileExport>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionKind {
+    Handler,
+    DispatchArm,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstructionExport {
+    pub name: String,
+    pub kind: InstructionKind,
+    pub file: PathBuf,
+    pub line: u32,
+    pub executed: bool,
+    pub invocations: Option<Invocations>,
+    pub functions: Vec<FunctionExport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionExport {
+    pub name: String,
+    pub file: PathBuf,
+    pub start_line: u32,
+    pub end_line: u32,
+    pub executed: bool,
+    pub executions: Option<u64>,
+    pub lines: Counter,
+    pub branches: Counter,
+    pub line_hits: BTreeMap<u32, u64>,
+    pub branch_hits: BTreeMap<u32, Vec<bool>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorExport {
+    pub name: String,
+    pub code: u32,
+    pub file: PathBuf,
+    pub line: u32,
+    pub triggered: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileExport {
+    pub path: PathBuf,
+    pub totals: Stats,
+}
+
+/// The zest-specific analyses of a report, which the export is built from
+#[derive(Clone, Copy)]
+pub struct Analyses<'a> {
+    pub instruction_coverage: &'a InstructionCoverage,
+    pub dispatch_coverage: &'a DispatchCoverage,
+    pub function_span_coverage: &'a FunctionSpanCoverage,
+    pub error_coverage: &'a CustomErrorCoverage,
+}
+
+impl Export {
+    pub fn new(
+        results: &[ResultTuple],
+        workspace: &Workspace,
+        contract_styles: &ContractStyles,
+        branch: bool,
+        analyses: Analyses,
+    ) -> eyre::Result<Self> {
+        let program_ids = program_ids(results, workspace)?;
+        let mut programs = BTreeMap::<String, ProgramExport>::new();
+
+        for (path, rel_path, result) in results {
+            let name = program_name(workspace, path, rel_path);
+            let program = programs.entry(name.clone()).or_insert_with(|| ProgramExport {
+                program_id: program_ids.get(&name).cloned(),
+                name,
+                contract_style: contract_styles.for_path(path),
+                totals: Stats::default(),
+                instructions: vec![],
+                functions: vec![],
+                errors: vec![],
+                files: vec![],
+            });
+
+            let totals = Stats::from_result(result);
+            program.totals += totals;
+            program.files.push(FileExport {
+                path: rel_path.clone(),
+                totals,
+            });
+
+            program.functions.extend(
+                analyses
+                    .function_span_coverage
+                    .functions
+                    .iter()
+                    .filter(|function| function.rel_path == *rel_path)
+                    .map(|function| FunctionExport {
+                        name: function.name.clone(),
+                        file: function.rel_path.clone(),
+                        start_line: function.start,
+                        end_line: function.end,
+                        executed: function.executed,
+                        executions: function.executions,
+                        lines: function.lines,
+                        branches: function.branches,
+                        line_hits: result
+                            .lines
+                            .range(function.start..=function.end)
+                            .map(|(line, hits)| (*line, *hits))
+                            .collect(),
+                        branch_hits: result
+                            .branches
+                            .range(function.start..=function.end)
+                            .map(|(line, taken)| (*line, taken.clone()))
+                            .collect(),
+                    }),
+            );
+        }
+
+        for program in programs.values_mut() {
+            program.instructions = instructions(program, &analyses);
+            // NOTE: the functions implementing an instruction are only listed under it
+            let nested = program
+                .instructions
+                .iter()
+                .flat_map(|instruction| &instruction.functions)
+                .map(|function| (function.file.clone(), function.start_line))
+                .collect::<HashSet<_>>();
+            program
+                .functions
+                .retain(|function| !nested.contains(&(function.file.clone(), function.start_line)));
+            program.errors = analyses
+                .error_coverage
+                .errors
+                .iter()
+                .filter(|error| error.program == program.name)
+                .map(|error| ErrorExport {
+                    name: error.name.clone(),
+                    code: error.code,
+                    file: error.rel_path.clone(),
+                    line: error.line,
+                    triggered: error.triggered,
+                })
+                .collect();
+        }
+
+        let programs = programs.into_values().collect::<Vec<_>>();
+        let mut totals = Stats::default();
+        programs.iter().for_each(|program| totals += program.totals);
+
+        Ok(Self {
+            schema_version: SCHEMA_VERSION,
+            zest_version: env!("CARGO_PKG_VERSION"),
+            branch,
+            totals,
+            programs,
+        })
+    }
+
+    pub fn write(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Could not write {}", path.display()))
+    }
+}
+
+impl ProgramExport {
+    /// All of the contract functions of the program (the ones implementing its instructions
+    /// and the others), ordered by their location
+    pub fn all_functions(&self) -> Vec<&FunctionExport> {
+        self.instructions
+            .iter()
+            .flat_map(|instruction| &instruction.functions)
+            .chain(&self.functions)
+            .unique_by(|function| (&function.file, function.start_line))
+            .sorted_by_key(|function| (&function.file, function.start_line))
+            .collect()
+    }
+}
+
+/// The `#[program]` handlers (Anchor) or the dispatched instruction enum variants (native) of
+/// `program`, along with the functions implementing them
+fn instructions(
+    program: &ProgramExport,
+    analyses: &Analyses,
+) -> Vec<InstructionExport> {
+    let handlers = analyses
+        .instruction_coverage
+        .handlers
+        .iter()
+        .filter(|handler| handler.program == program.name)
+        .map(|handler| InstructionExport {
+            name: handler.instruction(),
+            kind: InstructionKind::Handler,
+            file: handler.rel_path.clone(),
+            line: handler.line,
+            executed: handler.line_hits > 0 || handler.invocations.total > 0,
+            invocations: Some(handler.invocations),
+            // NOTE: the handler's own function (the one starting on its line)
+            functions: program
+                .functions
+                .iter()
+                .filter(|function| {
+                    function.file == handler.rel_path && function.start_line == handler.line
+                })
+                .cloned()
+                .collect(),
+        });
+
+    let arms = analyses
+        .dispatch_coverage
+        .variants
+        .iter()
+        .filter(|variant| variant.program == program.name)
+        .map(|variant| InstructionExport {
+            name: variant.variant.clone(),
+            kind: InstructionKind::DispatchArm,
+            file: variant.rel_path.clone(),
+            line: variant.line,
+            executed: variant.hits.map_or(false, |hits| hits > 0),
+            invocations: None,
+            // NOTE: the program's functions called by the arm (by their unqualified name)
+            functions: program
+                .functions
+                .iter()
+                .filter(|function| {
+                    // NOTE: without the `#2`-style suffix of colliding names
+                    let name = function.name.split('#').next().unwrap_or(&function.name);
+                    let name = name.rsplit("::").next().unwrap_or(name);
+                    variant.calls.iter().any(|call| call == name)
+                })
+                .cloned()
+                .collect(),
+        });
+
+    handlers.chain(arms).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_versioned_shape() {
+        let function = |name: &str, start_line, executed| FunctionExport {
+            name: name.to_string(),
+            file: PathBuf::from("programs/counter/src/lib.rs"),
+            start_line,
+            end_line: start_line + 2,
+            executed,
+            executions: None,
+            lines: Counter::new(executed as usize, 1),
+            branches: Counter::default(),
+            line_hits: BTreeMap::from([(start_line, executed as u64)]),
+            branch_hits: BTreeMap::new(),
+        };
+        let totals = Stats {
+            lines: Counter::new(1, 2),
+            functions: Counter::new(1, 2),
+            branches: Counter::default(),
+        };
+        let export = Export {
+            schema_version: SCHEMA_VERSION,
+            zest_version: "0.1.0",
+            branch: false,
+            totals,
+            programs: vec![ProgramExport {
+                name: "counter".to_string(),
+                contract_style: ContractStyle::Anchor,
+                program_id: None,
+                totals,
+                instructions: vec![InstructionExport {
+                    name: "Increment".to_string(),
+                    kind: InstructionKind::Handler,
+                    file: PathBuf::from("programs/counter/src/lib.rs"),
+                    line: 10,
+                    executed: true,
+                    invocations: Some(Invocations {
+                        total: 1,
+                        succeeded: 1,
+                        failed: 0,
+                    }),
+                    functions: vec![function("counter::increment", 10, true)],
+                }],
+                functions: vec![function("helper", 20, false)],
+                errors: vec![],
+                files: vec![FileExport {
+                    path: PathBuf::from("programs/counter/src/lib.rs"),
+                    totals,
+                }],
+            }],
+        };
+
+        let counter = |covered, total| serde_json::json!({ "covered": covered, "total": total });
+        let stats = serde_json::json!({
+            "lines": counter(1, 2),
+            "functions": counter(1, 2),
+            "branches": counter(0, 0),
+        });
+        let function = |name: &str, start_line: u32, hits: u64| {
+            serde_json::json!({
+                "name": name,
+                "file": "programs/counter/src/lib.rs",
+                "start_line": start_line,
+                "end_line": start_line + 2,
+                "executed": hits > 0,
+                "executions": null,
+                "lines": counter(hits, 1),
+                "branches": counter(0, 0),
+                "line_hits": { start_line.to_string(): hits },
+                "branch_hits": {},
+            })
+        };
+        assert_eq!(
+            serde_json::to_value(export).unwrap(),
+            serde_json::json!({
+                "schema_version": 1,
+                "zest_version": "0.1.0",
+                "branch": false,
+                "totals": stats,
+                "programs": [{
+                    "name": "counter",
+                    "contract_style": "anchor",
+                    "program_id": null,
+                    "totals": stats,
+                    "instructions": [{
+                        "name": "Increment",
+                        "kind": "handler",
+                        "file": "programs/counter/src/lib.rs",
+                        "line": 10,
+                        "executed": true,
+                        "invocations": { "total": 1, "succeeded": 1, "failed": 0 },
+                        "functions": [function("counter::increment", 10, 1)],
+                    }],
+                    "functions": [function("helper", 20, 0)],
+                    "errors": [],
+                    "files": [{ "path": "programs/counter/src/lib.rs", "totals": stats }],
+                }],
+            })
+        );
+    }
+}
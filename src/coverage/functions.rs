@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use eyre::Context;
+use grcov::ResultTuple;
+use serde::Serialize;
+
+use crate::{from_grcov::ExecutionCounts, parsing::extract_functions};
+
+use super::{
+    contract_style::ContractStyles, instructions::program_name, stats::Counter,
+    workspace::Workspace,
+};
+
+/// Line and branch coverage over the whole span (signature and body) of a single function
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionSpan {
+    pub program: String,
+    pub name: String,
+    pub rel_path: PathBuf,
+    pub start: u32,
+    pub end: u32,
+    /// Whether one of the function's records was executed with `FunctionCoverage::Records`,
+    /// else whether the line of its signature has hits
+    pub executed: bool,
+    /// How many times the function was executed, summed over its function records (only
+    /// known with `FunctionCoverage::Records`)
+    pub executions: Option<u64>,
+    pub lines: Counter,
+    pub branches: Counter,
+}
+
+/// Span-based coverage of the contract functions
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FunctionSpanCoverage {
+    pub functions: Vec<FunctionSpan>,
+}
+
+impl FunctionSpanCoverage {
+    /// Find the contract functions in the files of `results` (the same ones as the function
+    /// coverage) and count the covered lines and branches within each of them
+    ///
+    /// `execution_counts` are only given with `FunctionCoverage::Records`, in which case
+    /// `results`' functions are the merged function records
+    pub fn new(
+        results: &[ResultTuple],
+        workspace: &Workspace,
+        contract_styles: &ContractStyles,
+        execution_counts: Option<&ExecutionCounts>,
+    ) -> eyre::Result<Self> {
+        let mut functions = vec![];
+
+        for (path, rel_path, result) in results {
+            let query = contract_styles.for_path(path).function_query();
+            let spans = extract_functions(path, query)
+                .with_context(|| format!("Could not parse {}", path.display()))?;
+            if spans.is_empty() {
+                continue;
+            }
+
+            let program = program_name(workspace, path, rel_path);
+
+            for (name, (start, end)) in spans {
+                // NOTE: `Point`'s `row` is 0-indexed while `CovResult`'s `lines` are 1-indexed
+                let (start, end) = (start.row as u32 + 1, end.row as u32 + 1);
+                // NOTE: excluded (or not compiled)
+                if result.lines.range(start..=end).next().is_none() {
+                    continue;
+                }
+                // NOTE: the records starting where the function does (the others are e.g. its
+                //       closures)
+                let records = || {
+                    result
+                        .functions
+                        .iter()
+                        .filter(move |(_, function)| function.start == start)
+                };
+                let lines = result.lines.range(start..=end).map(|(_, hits)| *hits);
+                let branches = result
+                    .branches
+                    .range(start..=end)
+                    .flat_map(|(_, taken)| taken.iter().copied());
+
+                functions.push(FunctionSpan {
+                    program: program.clone(),
+                    name,
+                    rel_path: rel_path.clone(),
+                    start,
+                    end,
+                    executed: match execution_counts {
+                        Some(_) => records().any(|(_, function)| function.executed),
+                        None => result.lines.get(&start).map_or(false, |hits| *hits > 0),
+                    },
+                    executions: execution_counts.map(|counts| {
+                        records()
+                            .filter_map(|(name, _)| counts.get(&(path.clone(), name.clone())))
+                            .sum()
+                    }),
+                    lines: lines.fold(Counter::default(), |mut counter, hits| {
+                        counter += Counter::new((hits > 0) as usize, 1);
+                        counter
+                    }),
+                    branches: branches.fold(Counter::default(), |mut counter, taken| {
+                        counter += Counter::new(taken as usize, 1);
+                        counter
+                    }),
+                });
+            }
+        }
+
+        functions.sort_by(|a, b| {
+            (&a.program, &a.rel_path, a.start).cmp(&(&b.program, &b.rel_path, b.start))
+        });
+
+        Ok(Self { functions })
+    }
+}
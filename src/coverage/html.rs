@@ -0,0 +1,734 @@
+//! zest's HTML report: an overview of the programs, a page per program with its instructions,
+//! functions, errors and files, a page per file with its annotated source (and the line/branch
+//! coverage over each function's span), and a page per analysis (instruction handlers, dispatch
+//! arms, custom errors, diff, per-test coverage and the failed tests), all linked from the
+//! navigation bar
+//!
+//! The pages are rendered with `tera` from the built-in templates (`base.html`, `index.html`,
+//! `program.html`, `source.html` and the analyses' pages, e.g. `instructions.html`), any of which
+//! can be replaced by a file of the same name in the `html_templates` directory (which may also
+//! contain additional templates, e.g. macros). Every page gets `export` (see [Export]),
+//! `zest_version`, `pages` (the `{file, title}` of the analyses' pages) and `grcov_html` (whether
+//! grcov's HTML report was generated too), the program and source pages also get `program` and
+//! `functions` (all of its functions, nested under its instructions or not). Besides tera's own,
+//! the templates can use the `percent` filter (on a `{covered, total}` counter) and the
+//! `program_page`/`source_page` filters (on a program name/file path)
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use grcov::ResultTuple;
+use serde::Serialize;
+use tera::{Tera, Value};
+
+use super::{
+    diff::{self, DiffCoverage},
+    dispatch::{self, DispatchCoverage},
+    errors::{self, CustomErrorCoverage},
+    export::{Analyses, Export},
+    instructions::{self, InstructionCoverage},
+    per_test::{self, TestMatrix},
+    stats::Counter,
+    test_output,
+};
+
+pub const DIR_NAME: &str = "zest-html";
+
+/// Everything the report is rendered from
+pub struct Report<'a> {
+    pub export: &'a Export,
+    pub analyses: Analyses<'a>,
+    pub results: &'a [ResultTuple],
+    pub source_root: &'a Path,
+    pub diff_coverage: Option<&'a DiffCoverage>,
+    pub test_matrix: Option<&'a TestMatrix>,
+    pub failed_tests: &'a [String],
+    /// Whether grcov's HTML report (`../html`) was generated too, for linking to it
+    pub grcov_html: bool,
+}
+
+/// A page of the navigation bar
+#[derive(Debug, Clone, Serialize)]
+struct Page {
+    file: &'static str,
+    title: &'static str,
+}
+
+/// An annotated line of a source page
+#[derive(Debug, Clone, Serialize)]
+struct SourceLine {
+    number: u32,
+    text: String,
+    /// `None` if the line is not instrumented
+    hits: Option<u64>,
+    branches: Option<Counter>,
+    /// `covered`, `uncovered`, `partial` (some branches never taken) or `none`
+    status: &'static str,
+}
+
+pub fn write(
+    report: &Report,
+    templates_dir: Option<&Path>,
+    dir: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    let tera = templates(templates_dir)?;
+    let Analyses {
+        instruction_coverage,
+        dispatch_coverage,
+        error_coverage,
+        ..
+    } = report.analyses;
+
+    // NOTE: (page, its own context) of each analysis with anything to show
+    let mut pages = vec![];
+    if !instruction_coverage.handlers.is_empty() {
+        pages.push((
+            Page {
+                file: InstructionCoverage::HTML_FILE_NAME,
+                title: "Instructions",
+            },
+            instruction_coverage.html_context(),
+        ));
+    }
+    if !dispatch_coverage.variants.is_empty() {
+        pages.push((
+            Page {
+                file: DispatchCoverage::HTML_FILE_NAME,
+                title: "Dispatch",
+            },
+            dispatch_coverage.html_context(),
+        ));
+    }
+    if !error_coverage.errors.is_empty() {
+        pages.push((
+            Page {
+                file: CustomErrorCoverage::HTML_FILE_NAME,
+                title: "Errors",
+            },
+            error_coverage.html_context(),
+        ));
+    }
+    if let Some(diff_coverage) = report.diff_coverage {
+        pages.push((
+            Page {
+                file: DiffCoverage::HTML_FILE_NAME,
+                title: "Diff",
+            },
+            diff_coverage.html_context(report.source_root),
+        ));
+    }
+    if let Some(test_matrix) = report.test_matrix {
+        pages.push((
+            Page {
+                file: TestMatrix::HTML_FILE_NAME,
+                title: "Per test",
+            },
+            test_matrix.html_context(report.source_root),
+        ));
+    }
+    if !report.failed_tests.is_empty() {
+        pages.push((
+            Page {
+                file: test_output::FAILED_TESTS_HTML_FILE_NAME,
+                title: "Failed tests",
+            },
+            test_output::failed_tests_html_context(report.failed_tests),
+        ));
+    }
+
+    let mut context = tera::Context::new();
+    context.insert("export", report.export);
+    context.insert("zest_version", env!("CARGO_PKG_VERSION"));
+    context.insert(
+        "pages",
+        &pages.iter().map(|(page, _)| page).collect::<Vec<_>>(),
+    );
+    context.insert("grcov_html", &report.grcov_html);
+
+    render(&tera, "index.html", &context, dir.join("index.html"))?;
+
+    for (page, page_context) in pages {
+        let mut context = context.clone();
+        context.extend(page_context);
+        render(&tera, page.file, &context, dir.join(page.file))?;
+    }
+
+    for program in &report.export.programs {
+        context.insert("program", program);
+        context.insert("functions", &program.all_functions());
+        render(
+            &tera,
+            "program.html",
+            &context,
+            dir.join(page("program", &program.name)),
+        )?;
+
+        for file in &program.files {
+            let Some((path, _, result)) = report
+                .results
+                .iter()
+                .find(|(_, rel_path, _)| *rel_path == file.path)
+            else {
+                continue;
+            };
+            let source = fs::read_to_string(path)
+                .with_context(|| format!("Could not read {}", path.display()))?;
+
+            context.insert("file", file);
+            context.insert("lines", &source_lines(&source, result));
+            render(
+                &tera,
+                "source.html",
+                &context,
+                dir.join(page("source", &file.path.display().to_string())),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The built-in templates, overridden by (and extended with) the `.html` files in `templates_dir`
+fn templates(templates_dir: Option<&Path>) -> eyre::Result<Tera> {
+    let mut tera = Tera::default();
+    tera.add_raw_templates([
+        ("base.html", BASE_TEMPLATE),
+        ("index.html", INDEX_TEMPLATE),
+        ("program.html", PROGRAM_TEMPLATE),
+        ("source.html", SOURCE_TEMPLATE),
+        (InstructionCoverage::HTML_FILE_NAME, instructions::HTML_TEMPLATE),
+        (DispatchCoverage::HTML_FILE_NAME, dispatch::HTML_TEMPLATE),
+        (CustomErrorCoverage::HTML_FILE_NAME, errors::HTML_TEMPLATE),
+        (DiffCoverage::HTML_FILE_NAME, diff::HTML_TEMPLATE),
+        (TestMatrix::HTML_FILE_NAME, per_test::HTML_TEMPLATE),
+        (
+            test_output::FAILED_TESTS_HTML_FILE_NAME,
+            test_output::FAILED_TESTS_HTML_TEMPLATE,
+        ),
+    ])?;
+
+    if let Some(templates_dir) = templates_dir {
+        let mut files = vec![];
+        for entry in fs::read_dir(templates_dir)
+            .with_context(|| format!("Could not read {}", templates_dir.display()))?
+        {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "html")
+            {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                files.push((path, Some(name)));
+            }
+        }
+        tera.add_template_files(files).wrap_err_with(|| {
+            format!(
+                "Could not load the templates in {}",
+                templates_dir.display()
+            )
+        })?;
+    }
+
+    tera.register_filter("percent", percent_filter);
+    tera.register_filter(
+        "program_page",
+        |value: &Value, _: &HashMap<String, Value>| {
+            Ok(Value::String(page(
+                "program",
+                &tera::from_value::<String>(value.clone())?,
+            )))
+        },
+    );
+    tera.register_filter(
+        "source_page",
+        |value: &Value, _: &HashMap<String, Value>| {
+            Ok(Value::String(page(
+                "source",
+                &tera::from_value::<String>(value.clone())?,
+            )))
+        },
+    );
+
+    Ok(tera)
+}
+
+fn render(
+    tera: &Tera,
+    template: &str,
+    context: &tera::Context,
+    path: PathBuf,
+) -> eyre::Result<()> {
+    let html = tera
+        .render(template, context)
+        .with_context(|| format!("Could not render {}", template))?;
+    fs::write(&path, html).with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// The file name of the `kind` page of `name`, e.g. `source-programs_counter_src_lib.rs.html`
+///
+/// NOTE: `/` becomes `_`, any other byte which isn't alphanumeric, `-` or `.` (including `_`
+///       itself) is escaped as `~XX`, so that different names never share a page
+fn page(
+    kind: &str,
+    name: &str,
+) -> String {
+    let mut escaped = String::new();
+    for byte in name.bytes() {
+        match byte {
+            b'/' => escaped.push('_'),
+            _ if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.') => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("~{:02X}", byte)),
+        }
+    }
+    format!("{}-{}.html", kind, escaped)
+}
+
+/// `{covered, total}` -> e.g. `53.33%`, `-` if there is nothing to cover
+fn percent_filter(
+    value: &Value,
+    _: &HashMap<String, Value>,
+) -> tera::Result<Value> {
+    let counter = tera::from_value::<Counter>(value.clone())?;
+    Ok(Value::String(counter.percent().map_or_else(
+        || "-".to_string(),
+        |percent| format!("{:.2}%", percent),
+    )))
+}
+
+fn source_lines(
+    source: &str,
+    result: &grcov::CovResult,
+) -> Vec<SourceLine> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(index, text)| {
+            // NOTE: `CovResult`'s `lines` are 1-indexed
+            let number = index as u32 + 1;
+            let hits = result.lines.get(&number).copied();
+            let branches = result.branches.get(&number).map(|taken| {
+                Counter::new(taken.iter().filter(|taken| **taken).count(), taken.len())
+            });
+            let status = match (hits, branches) {
+                (Some(0), _) => "uncovered",
+                (_, Some(branches)) if branches.covered < branches.total => "partial",
+                (Some(_), _) => "covered",
+                (None, _) => "none",
+            };
+
+            SourceLine {
+                number,
+                text: text.to_string(),
+                hits,
+                branches,
+                status,
+            }
+        })
+        .collect()
+}
+
+#[rustfmt::skip]
+pub const BASE_TEMPLATE: &str = /* html */ r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>zest - {% block title %}coverage{% endblock title %}</title>
+  <style>
+    :root { --brand: #14f195; --brand-dark: #0b7a4b; --accent: #9945ff; }
+    body { font-family: sans-serif; margin: 0; color: #222; }
+    header { background: linear-gradient(90deg, var(--accent), var(--brand)); color: white; padding: 0.8em 2em; display: flex; align-items: center; gap: 2em; }
+    header .logo { font-size: 1.6em; font-weight: bold; letter-spacing: 0.05em; color: white; text-decoration: none; }
+    header nav { flex: 1; }
+    header nav a { color: white; }
+    header label { white-space: nowrap; }
+    main { margin: 1.5em 2em; }
+    footer { margin: 2em; color: #888; font-size: 0.8em; }
+    table { border-collapse: collapse; margin-bottom: 2em; width: 100%; }
+    th, td { border: 1px solid #ddd; padding: 0.2em 0.6em; text-align: left; }
+    th { background: #f4f4f4; }
+    td.number { text-align: right; }
+    tr.uncovered td { background: #fdd; }
+    tr.partial td { background: #ffd; }
+    table.source { font-family: monospace; font-size: 0.9em; }
+    table.source td { border: none; padding: 0 0.6em; white-space: pre; }
+    table.source td.gutter { color: #888; text-align: right; border-right: 1px solid #ddd; }
+    table.source tr.covered td.code { background: #e6ffed; }
+    table.source tr.handler td.code { font-weight: bold; }
+    table.source tr.function td { font-family: sans-serif; border-top: 1px solid #ddd; padding: 0.2em 0.6em; }
+    td.source { font-family: monospace; white-space: pre; }
+    td ul { margin: 0; padding-left: 1.2em; }
+    .breadcrumbs { color: #666; }
+    body.only-uncovered tr.covered, body.only-uncovered tr.none { display: none; }
+  </style>
+</head>
+<body>
+  <header>
+    <a class="logo" href="index.html">zest</a>
+    <nav>
+      <a href="index.html">Programs</a>
+      {% for page in pages %} | <a href="{{ page.file }}">{{ page.title }}</a>{% endfor %}
+      {% if grcov_html %} | <a href="../html/index.html">Files (grcov)</a>{% endif %}
+    </nav>
+    <label><input type="checkbox" id="only-uncovered"> Only uncovered</label>
+  </header>
+  <main>
+    <p class="breadcrumbs">{% block nav %}{% endblock nav %}</p>
+    {% block content %}{% endblock content %}
+  </main>
+  <footer>Generated by zest {{ zest_version }}</footer>
+  <script>
+    // NOTE: the filter is remembered across the pages
+    const checkbox = document.getElementById("only-uncovered");
+    const apply = () => document.body.classList.toggle("only-uncovered", checkbox.checked);
+    checkbox.checked = localStorage.getItem("zest-only-uncovered") === "true";
+    apply();
+    checkbox.addEventListener("change", () => {
+      localStorage.setItem("zest-only-uncovered", checkbox.checked);
+      apply();
+    });
+  </script>
+</body>
+</html>
+"#;
+
+#[rustfmt::skip]
+pub const INDEX_TEMPLATE: &str = /* html */ r#"
+{% extends "base.html" %}
+{% block title %}programs{% endblock title %}
+{% block content %}
+  <h1>Programs</h1>
+  <table>
+    <tr><th>Program</th><th>Style</th><th>Program id</th><th>Instructions</th><th>Lines</th><th>Functions</th><th>Branches</th></tr>
+    {% for program in export.programs %}
+    <tr class="{% if program.totals.lines.covered == program.totals.lines.total and program.totals.branches.covered == program.totals.branches.total %}covered{% else %}uncovered{% endif %}">
+      <td><a href="{{ program.name | program_page }}">{{ program.name }}</a></td>
+      <td>{{ program.contract_style }}</td>
+      <td><code>{{ program.program_id | default(value="-") }}</code></td>
+      <td class="number">{{ program.instructions | filter(attribute="executed", value=true) | length }}/{{ program.instructions | length }}</td>
+      <td class="number">{{ program.totals.lines | percent }} ({{ program.totals.lines.covered }}/{{ program.totals.lines.total }})</td>
+      <td class="number">{{ program.totals.functions | percent }} ({{ program.totals.functions.covered }}/{{ program.totals.functions.total }})</td>
+      <td class="number">{{ program.totals.branches | percent }}</td>
+    </tr>
+    {% endfor %}
+    <tr>
+      <th>Total</th><th></th><th></th><th></th>
+      <th class="number">{{ export.totals.lines | percent }} ({{ export.totals.lines.covered }}/{{ export.totals.lines.total }})</th>
+      <th class="number">{{ export.totals.functions | percent }} ({{ export.totals.functions.covered }}/{{ export.totals.functions.total }})</th>
+      <th class="number">{{ export.totals.branches | percent }}</th>
+    </tr>
+  </table>
+{% endblock content %}
+"#;
+
+#[rustfmt::skip]
+pub const PROGRAM_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}{{ program.name }}{% endblock title %}
+{% block nav %}<a href="index.html">Programs</a> / {{ program.name }}{% endblock nav %}
+{% block content %}
+  <h1>{{ program.name }}</h1>
+  <p>
+    {{ program.contract_style }} program{% if program.program_id %} <code>{{ program.program_id }}</code>{% endif %},
+    lines {{ program.totals.lines | percent }}, functions {{ program.totals.functions | percent }}, branches {{ program.totals.branches | percent }}
+  </p>
+
+  {% if program.instructions %}
+  <h2>Instructions</h2>
+  <table>
+    <tr><th>Instruction</th><th>Kind</th><th>Location</th><th>Executed</th><th>Invocations</th><th>Functions</th></tr>
+    {% for instruction in program.instructions %}
+    <tr class="{% if instruction.executed %}covered{% else %}uncovered{% endif %}">
+      <td>{{ instruction.name }}</td>
+      <td>{% if instruction.kind == "handler" %}handler{% else %}dispatch arm{% endif %}</td>
+      <td><a href="{{ instruction.file | source_page }}#L{{ instruction.line }}">{{ instruction.file }}:{{ instruction.line }}</a></td>
+      <td>{% if instruction.executed %}yes{% else %}no{% endif %}</td>
+      <td class="number">{% if instruction.invocations %}{{ instruction.invocations.total }} ({{ instruction.invocations.succeeded }} ok, {{ instruction.invocations.failed }} failed){% else %}-{% endif %}</td>
+      <td>{% for function in instruction.functions %}<a href="{{ function.file | source_page }}#L{{ function.start_line }}"><code>{{ function.name }}</code></a>{% if not loop.last %}, {% endif %}{% endfor %}</td>
+    </tr>
+    {% endfor %}
+  </table>
+  {% endif %}
+
+  {% if program.errors %}
+  <h2>Custom errors</h2>
+  <table>
+    <tr><th>Error</th><th>Code</th><th>Location</th><th>Triggered</th></tr>
+    {% for error in program.errors %}
+    <tr class="{% if error.triggered > 0 %}covered{% else %}uncovered{% endif %}">
+      <td><code>{{ error.name }}</code></td>
+      <td class="number">{{ error.code }}</td>
+      <td><a href="{{ error.file | source_page }}#L{{ error.line }}">{{ error.file }}:{{ error.line }}</a></td>
+      <td class="number">{{ error.triggered }}</td>
+    </tr>
+    {% endfor %}
+  </table>
+  {% endif %}
+
+  {% if functions %}
+  <h2>Functions</h2>
+  <p>Lines and branches covered over each function's whole span</p>
+  <table>
+    {% set with_executions = functions | filter(attribute="executions") | length > 0 %}
+    <tr><th>Function</th><th>Location</th>{% if with_executions %}<th>Executions</th>{% endif %}<th>Lines</th><th>Branches</th></tr>
+    {% for function in functions %}
+    <tr class="{% if not function.executed %}uncovered{% elif function.lines.covered < function.lines.total or function.branches.covered < function.branches.total %}partial{% else %}covered{% endif %}">
+      <td><code>{{ function.name }}</code></td>
+      <td><a href="{{ function.file | source_page }}#L{{ function.start_line }}">{{ function.file }}:{{ function.start_line }}-{{ function.end_line }}</a></td>
+      {% if with_executions %}<td class="number">{{ function.executions }}</td>{% endif %}
+      <td class="number">{{ function.lines | percent }} ({{ function.lines.covered }}/{{ function.lines.total }})</td>
+      <td class="number">{% if function.branches.total > 0 %}{{ function.branches | percent }} ({{ function.branches.covered }}/{{ function.branches.total }}){% else %}-{% endif %}</td>
+    </tr>
+    {% endfor %}
+  </table>
+  {% endif %}
+
+  <h2>Files</h2>
+  <table>
+    <tr><th>File</th><th>Lines</th><th>Functions</th><th>Branches</th></tr>
+    {% for file in program.files %}
+    <tr class="{% if file.totals.lines.covered == file.totals.lines.total and file.totals.branches.covered == file.totals.branches.total %}covered{% else %}uncovered{% endif %}">
+      <td><a href="{{ file.path | source_page }}">{{ file.path }}</a></td>
+      <td class="number">{{ file.totals.lines | percent }} ({{ file.totals.lines.covered }}/{{ file.totals.lines.total }})</td>
+      <td class="number">{{ file.totals.functions | percent }} ({{ file.totals.functions.covered }}/{{ file.totals.functions.total }})</td>
+      <td class="number">{{ file.totals.branches | percent }}</td>
+    </tr>
+    {% endfor %}
+  </table>
+{% endblock content %}
+"##;
+
+#[rustfmt::skip]
+pub const SOURCE_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}{{ file.path }}{% endblock title %}
+{% block nav %}<a href="index.html">Programs</a> / <a href="{{ program.name | program_page }}">{{ program.name }}</a> / {{ file.path }}{% endblock nav %}
+{% block content %}
+  <h1>{{ file.path }}</h1>
+  <p>Lines {{ file.totals.lines | percent }}, functions {{ file.totals.functions | percent }}, branches {{ file.totals.branches | percent }}</p>
+  {% set handler_lines = program.instructions | filter(attribute="file", value=file.path) | map(attribute="line") %}
+  {% set file_functions = functions | filter(attribute="file", value=file.path) %}
+  <table class="source">
+    <tr><th>Line</th><th>Hits</th><th>Branches</th><th></th></tr>
+    {% for line in lines %}
+    {% for function in file_functions %}{% if function.start_line == line.number %}
+    <tr class="function {% if not function.executed %}uncovered{% elif function.lines.covered < function.lines.total or function.branches.covered < function.branches.total %}partial{% else %}covered{% endif %}">
+      <td class="gutter"></td>
+      <td class="gutter" colspan="2">{{ function.lines | percent }}</td>
+      <td class="code"><code>{{ function.name }}</code>: lines {{ function.lines.covered }}/{{ function.lines.total }}{% if function.branches.total > 0 %}, branches {{ function.branches.covered }}/{{ function.branches.total }}{% endif %}</td>
+    </tr>
+    {% endif %}{% endfor %}
+    <tr id="L{{ line.number }}" class="{{ line.status }}{% if line.number in handler_lines %} handler{% endif %}">
+      <td class="gutter"><a href="#L{{ line.number }}">{{ line.number }}</a></td>
+      <td class="gutter">{% if line.hits is number %}{{ line.hits }}{% endif %}</td>
+      <td class="gutter">{% if line.branches %}{{ line.branches.covered }}/{{ line.branches.total }}{% endif %}</td>
+      <td class="code">{{ line.text }}</td>
+    </tr>
+    {% endfor %}
+  </table>
+{% endblock content %}
+"##;
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use grcov::CovResult;
+
+    use super::*;
+    use crate::coverage::{
+        export::{FileExport, FunctionExport, InstructionExport, InstructionKind, ProgramExport},
+        instructions::{HandlerCoverage, Invocations},
+        stats::Stats,
+        ContractStyle,
+    };
+
+    #[test]
+    fn renders_percent_and_page_names() {
+        let mut tera = templates(None).unwrap();
+        let mut context = tera::Context::new();
+        context.insert("lines", &Counter::new(16, 30));
+        context.insert("none", &Counter::new(0, 0));
+        context.insert("file", "programs/counter/src/lib.rs");
+
+        tera.add_raw_template(
+            "test",
+            "{{ lines | percent }} {{ none | percent }} {{ file | source_page }}",
+        )
+        .unwrap();
+        assert_eq!(
+            tera.render("test", &context).unwrap(),
+            "53.33% - source-programs_counter_src_lib.rs.html"
+        );
+
+        assert_eq!(page("source", "src/a/b.rs"), "source-src_a_b.rs.html");
+        assert_eq!(page("source", "src/a_b.rs"), "source-src_a~5Fb.rs.html");
+    }
+
+    #[test]
+    fn renders_linked_pages() {
+        let project = tempfile::tempdir().unwrap();
+        let path = project.path().join("src/lib.rs");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            "#[program]\npub mod counter {\n    pub fn increment() {\n        helper();\n    }\n}\n",
+        )
+        .unwrap();
+        let rel_path = PathBuf::from("src/lib.rs");
+        let results = vec![(
+            path.clone(),
+            rel_path.clone(),
+            CovResult {
+                lines: [(3, 2), (4, 2), (6, 0)].into_iter().collect(),
+                branches: Default::default(),
+                functions: Default::default(),
+            },
+        )];
+
+        let totals = Stats {
+            lines: Counter::new(2, 3),
+            ..Default::default()
+        };
+        let export = Export {
+            schema_version: 2,
+            zest_version: "0.1.0",
+            branch: false,
+            totals,
+            programs: vec![ProgramExport {
+                name: "counter".to_string(),
+                contract_style: ContractStyle::Anchor,
+                program_id: None,
+                totals,
+                instructions: vec![InstructionExport {
+                    name: "Increment".to_string(),
+                    kind: InstructionKind::Handler,
+                    file: rel_path.clone(),
+                    line: 3,
+                    executed: true,
+                    invocations: None,
+                    functions: vec![FunctionExport {
+                        name: "counter::increment".to_string(),
+                        file: rel_path.clone(),
+                        start_line: 3,
+                        end_line: 5,
+                        executed: true,
+                        executions: None,
+                        lines: Counter::new(2, 2),
+                        branches: Counter::default(),
+                        line_hits: Default::default(),
+                        branch_hits: Default::default(),
+                    }],
+                }],
+                functions: vec![],
+                errors: vec![],
+                files: vec![FileExport {
+                    path: rel_path.clone(),
+                    totals,
+                }],
+            }],
+        };
+        let instruction_coverage = InstructionCoverage {
+            handlers: vec![HandlerCoverage {
+                program: "counter".to_string(),
+                name: "increment".to_string(),
+                rel_path: rel_path.clone(),
+                line: 3,
+                end_line: 5,
+                line_hits: 2,
+                lines: Counter::new(2, 2),
+                invocations: Invocations::default(),
+            }],
+        };
+
+        let dir = project.path().join(DIR_NAME);
+        write(
+            &Report {
+                export: &export,
+                analyses: Analyses {
+                    instruction_coverage: &instruction_coverage,
+                    dispatch_coverage: &Default::default(),
+                    function_span_coverage: &Default::default(),
+                    error_coverage: &Default::default(),
+                },
+                results: &results,
+                source_root: project.path(),
+                diff_coverage: None,
+                test_matrix: None,
+                failed_tests: &[],
+                grcov_html: false,
+            },
+            None,
+            &dir,
+        )
+        .unwrap();
+        let read = |page: &str| fs::read_to_string(dir.join(page)).unwrap();
+
+        let index = read("index.html");
+        assert!(index.contains(r#"<a href="instructions.html">"#));
+        assert!(index.contains(r#"href="program-counter.html""#));
+
+        let program = read("program-counter.html");
+        assert!(program.contains(r#"href="source-src_lib.rs.html#L3""#));
+        assert!(program.contains(r#"<a href="index.html">Programs</a> / counter"#));
+
+        let source = read("source-src_lib.rs.html");
+        assert!(source.contains(r#"<tr id="L3" class="covered handler">"#));
+        assert!(source.contains(r#"<tr id="L4" class="covered">"#));
+        assert!(source.contains(r#"<tr id="L6" class="uncovered">"#));
+        assert!(source.contains(r#"<tr class="function covered">"#));
+        assert!(source.contains(r#"href="program-counter.html""#));
+
+        assert!(read("instructions.html").contains(r#"href="source-src_lib.rs.html#L3""#));
+    }
+
+    #[test]
+    fn renders_uncovered_diff() {
+        let export = Export {
+            schema_version: 1,
+            zest_version: "0.1.0",
+            branch: false,
+            totals: Stats::default(),
+            programs: vec![],
+        };
+        let diff_coverage = DiffCoverage {
+            base: "main".to_string(),
+            lines: Counter::new(0, 2),
+            files: vec![],
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            &Report {
+                export: &export,
+                analyses: Analyses {
+                    instruction_coverage: &Default::default(),
+                    dispatch_coverage: &Default::default(),
+                    function_span_coverage: &Default::default(),
+                    error_coverage: &Default::default(),
+                },
+                results: &[],
+                source_root: dir.path(),
+                diff_coverage: Some(&diff_coverage),
+                test_matrix: None,
+                failed_tests: &[],
+                grcov_html: false,
+            },
+            None,
+            dir.path(),
+        )
+        .unwrap();
+
+        let diff = fs::read_to_string(dir.path().join(DiffCoverage::HTML_FILE_NAME)).unwrap();
+        assert!(diff.contains("<p>0% (0/2 changed lines)</p>"));
+        assert!(!diff.contains("No changed program lines"));
+    }
+}
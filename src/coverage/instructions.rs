@@ -0,0 +1,380 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::AddAssign,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use grcov::ResultTuple;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+
+use crate::parsing::{extract_functions, FUNCTION_IN_PROGRAM_MODULE_QUERY};
+
+use super::{
+    contract_style::ContractStyles, stats::Counter, workspace::Workspace, ContractStyle,
+};
+
+lazy_static! {
+    static ref DECLARE_ID: Regex = Regex::new(r#"declare_id!\(\s*"(\w+)"\s*\)"#).unwrap();
+}
+
+/// The `declare_id!`ed program id of each workspace member (with one in the files of `results`)
+pub fn program_ids(
+    results: &[ResultTuple],
+    workspace: &Workspace,
+) -> eyre::Result<BTreeMap<String, String>> {
+    let mut program_ids = BTreeMap::new();
+
+    for (path, _, _) in results {
+        let Some(package) = workspace.members.iter().find(|package| package.contains(path))
+        else {
+            continue;
+        };
+        if program_ids.contains_key(&package.name) {
+            continue;
+        }
+
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        if let Some(captures) = DECLARE_ID.captures(&source) {
+            program_ids.insert(package.name.clone(), captures[1].to_string());
+        }
+    }
+
+    Ok(program_ids)
+}
+
+/// The package containing `path`, or `rel_path` if it is outside of all workspace members
+pub fn program_name(
+    workspace: &Workspace,
+    path: &Path,
+    rel_path: &Path,
+) -> String {
+    workspace
+        .members
+        .iter()
+        .find(|package| package.contains(path))
+        .map_or_else(|| rel_path.display().to_string(), |package| package.name.clone())
+}
+
+/// How many times an instruction was invoked, according to the program logs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Invocations {
+    pub total: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+}
+
+impl AddAssign for Invocations {
+    fn add_assign(&mut self, rhs: Self) {
+        self.total += rhs.total;
+        self.succeeded += rhs.succeeded;
+        self.failed += rhs.failed;
+    }
+}
+
+/// Invocations keyed by (program id, instruction name as logged)
+///
+/// The program id is `None` if the `Instruction: X` log was not inside of a logged invocation
+pub type InvocationLog = BTreeMap<(Option<String>, String), Invocations>;
+
+/// Parse the Anchor `Program log: Instruction: X` lines (and the surrounding
+/// `Program <id> invoke [n]`/`success`/`failed: ...` lines) in the output of the tests
+///
+/// NOTE: when tests run in parallel their logs interleave, so the success/failure of
+///       concurrent invocations of the same program may be attributed to the wrong
+///       instruction, the invocation counts themselves are exact
+pub fn parse_invocations(log: &str) -> InvocationLog {
+    let mut invocations = InvocationLog::new();
+    // NOTE: (program id, logged instruction) of the invocations in progress, CPIs nest
+    let mut stack: Vec<(String, Option<String>)> = vec![];
+
+    for line in log.lines() {
+        // NOTE: the lines are usually prefixed by the logger (timestamp, level, target)
+        let Some(index) = line.find("Program ") else {
+            continue;
+        };
+        let rest = &line[index + "Program ".len()..];
+
+        if let Some(instruction) = rest.strip_prefix("log: Instruction: ") {
+            let instruction = instruction.trim().to_string();
+            let program_id = match stack.iter_mut().rev().find(|(_, logged)| logged.is_none()) {
+                Some((program_id, logged)) => {
+                    *logged = Some(instruction.clone());
+                    Some(program_id.clone())
+                }
+                None => None,
+            };
+            invocations.entry((program_id, instruction)).or_default().total += 1;
+            continue;
+        }
+
+        let Some((program_id, outcome)) = rest.split_once(' ') else {
+            continue;
+        };
+        if outcome.starts_with("invoke [") {
+            stack.push((program_id.to_string(), None));
+            continue;
+        }
+        let succeeded = match outcome {
+            "success" => true,
+            _ if outcome.starts_with("failed") => false,
+            _ => continue,
+        };
+
+        let Some(position) = stack.iter().rposition(|(id, _)| id == program_id) else {
+            continue;
+        };
+        let (program_id, logged) = stack.remove(position);
+        if let Some(instruction) = logged {
+            let entry = invocations.entry((Some(program_id), instruction)).or_default();
+            if succeeded {
+                entry.succeeded += 1;
+            } else {
+                entry.failed += 1;
+            }
+        }
+    }
+
+    invocations
+}
+
+/// Coverage of a single handler of an Anchor `#[program]` module
+#[derive(Debug, Clone, Serialize)]
+pub struct HandlerCoverage {
+    pub program: String,
+    pub name: String,
+    pub rel_path: PathBuf,
+    /// Line of the handler's signature
+    pub line: u32,
+    /// Last line of the handler
+    pub end_line: u32,
+    /// Hits of the handler's signature line (roughly how many times it was entered)
+    pub line_hits: u64,
+    /// Line coverage of the handler's body
+    pub lines: Counter,
+    pub invocations: Invocations,
+}
+
+impl HandlerCoverage {
+    /// The name Anchor logs for the handler
+    pub fn instruction(&self) -> String {
+        upper_camel_case(&self.name)
+    }
+}
+
+#[derive(Tabled)]
+struct HandlerRow {
+    #[tabled(rename = "Program")]
+    program: String,
+    #[tabled(rename = "Handler")]
+    handler: String,
+    #[tabled(rename = "Invocations")]
+    invocations: u64,
+    #[tabled(rename = "Succeeded")]
+    succeeded: u64,
+    #[tabled(rename = "Failed")]
+    failed: u64,
+    #[tabled(rename = "Line hits")]
+    line_hits: u64,
+    #[tabled(rename = "Lines")]
+    lines: String,
+}
+
+/// Per-handler coverage of the Anchor programs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InstructionCoverage {
+    pub handlers: Vec<HandlerCoverage>,
+}
+
+impl InstructionCoverage {
+    pub const HTML_FILE_NAME: &'static str = "instructions.html";
+
+    /// Find the handlers in the (Anchor) files of `results`, matching them to the logged
+    /// invocations by their (`UpperCamelCase`d) name and their program's `declare_id!`
+    pub fn new(
+        results: &[ResultTuple],
+        workspace: &Workspace,
+        contract_styles: &ContractStyles,
+        invocation_log: &InvocationLog,
+    ) -> eyre::Result<Self> {
+        let program_ids = program_ids(results, workspace)?;
+        let mut handlers = vec![];
+
+        for (path, rel_path, result) in results {
+            if contract_styles.for_path(path) != ContractStyle::Anchor {
+                continue;
+            }
+            let spans = extract_functions(path, &FUNCTION_IN_PROGRAM_MODULE_QUERY)?;
+            if spans.is_empty() {
+                continue;
+            }
+
+            let program = program_name(workspace, path, rel_path);
+            let program_id = program_ids.get(&program);
+
+            for (qualified_name, (start, end)) in spans {
+                // NOTE: the handlers are direct children of the `#[program]` module, so their
+                //       bare names are unique
+                let name = qualified_name
+                    .rsplit("::")
+                    .next()
+                    .unwrap_or(&qualified_name)
+                    .to_string();
+                // NOTE: `Point`'s `row` is 0-indexed while `CovResult`'s `lines` are 1-indexed
+                let (start, end) = (start.row as u32 + 1, end.row as u32 + 1);
+                let body = result
+                    .lines
+                    .range(start + 1..=end)
+                    .map(|(_, hits)| *hits)
+                    .collect::<Vec<_>>();
+                // NOTE: excluded (or not compiled)
+                if body.is_empty() && !result.lines.contains_key(&start) {
+                    continue;
+                }
+
+                let instruction = upper_camel_case(&name);
+                let mut invocations = Invocations::default();
+                invocation_log
+                    .iter()
+                    .filter(|((id, logged), _)| {
+                        *logged == instruction
+                            && (program_id.is_none() || id.is_none() || id.as_ref() == program_id)
+                    })
+                    .for_each(|(_, logged_invocations)| invocations += *logged_invocations);
+
+                handlers.push(HandlerCoverage {
+                    program: program.clone(),
+                    name,
+                    rel_path: rel_path.clone(),
+                    line: start,
+                    end_line: end,
+                    line_hits: result.lines.get(&start).copied().unwrap_or_default(),
+                    lines: Counter::new(
+                        body.iter().filter(|hits| **hits > 0).count(),
+                        body.len(),
+                    ),
+                    invocations,
+                });
+            }
+        }
+
+        handlers.sort_by(|a, b| (&a.program, a.line).cmp(&(&b.program, b.line)));
+
+        Ok(Self { handlers })
+    }
+
+    pub fn table(&self) -> Table {
+        Table::new(self.handlers.iter().map(|handler| HandlerRow {
+            program: handler.program.clone(),
+            handler: handler.name.clone(),
+            invocations: handler.invocations.total,
+            succeeded: handler.invocations.succeeded,
+            failed: handler.invocations.failed,
+            line_hits: handler.line_hits,
+            lines: match handler.lines.percent() {
+                Some(percent) => format!(
+                    "{:.2}% ({}/{})",
+                    percent, handler.lines.covered, handler.lines.total
+                ),
+                None => "-".to_string(),
+            },
+        }))
+    }
+
+    pub fn print(&self) {
+        eprintln!("Instruction coverage:");
+        eprintln!("{}", self.table());
+    }
+
+    /// For the instructions page of the HTML report
+    pub fn html_context(&self) -> tera::Context {
+        let mut context = tera::Context::new();
+        context.insert("handlers", &self.handlers);
+        context
+    }
+}
+
+/// The name Anchor logs for a handler, e.g. `initialize_v2` -> `InitializeV2`
+fn upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[rustfmt::skip]
+pub const HTML_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}instruction coverage{% endblock title %}
+{% block content %}
+  <h1>Instruction coverage</h1>
+  <table>
+    <tr>
+      <th>Program</th><th>Handler</th><th>File</th>
+      <th>Invocations</th><th>Succeeded</th><th>Failed</th><th>Line hits</th><th>Lines</th>
+    </tr>
+    {% for handler in handlers %}
+    <tr class="{% if handler.line_hits == 0 and handler.invocations.total == 0 %}uncovered{% else %}covered{% endif %}">
+      <td><a href="{{ handler.program | program_page }}">{{ handler.program }}</a></td>
+      <td><code>{{ handler.name }}</code></td>
+      <td><a href="{{ handler.rel_path | source_page }}#L{{ handler.line }}">{{ handler.rel_path }}:{{ handler.line }}</a></td>
+      <td class="number">{{ handler.invocations.total }}</td>
+      <td class="number">{{ handler.invocations.succeeded }}</td>
+      <td class="number">{{ handler.invocations.failed }}</td>
+      <td class="number">{{ handler.line_hits }}</td>
+      <td class="number">{{ handler.lines.covered }}/{{ handler.lines.total }}</td>
+    </tr>
+    {% endfor %}
+  </table>
+{% endblock content %}
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_invocations() {
+        let log = "\
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program Counter111 invoke [1]
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program log: Instruction: Initialize
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program 11111111111111111111111111111111 invoke [2]
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program 11111111111111111111111111111111 success
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program Counter111 consumed 5000 of 200000 compute units
+[2024-07-01T00:00:00Z DEBUG solana_runtime::message_processor::stable_log] Program Counter111 success
+Program Counter111 invoke [1]
+Program log: Instruction: Increment
+Program Counter111 failed: custom program error: 0x1770
+Program log: Instruction: Increment
+";
+
+        let invocations = parse_invocations(log);
+        let get = |id: Option<&str>, instruction: &str| {
+            invocations[&(id.map(str::to_string), instruction.to_string())]
+        };
+
+        assert_eq!(
+            get(Some("Counter111"), "Initialize"),
+            Invocations { total: 1, succeeded: 1, failed: 0 }
+        );
+        assert_eq!(
+            get(Some("Counter111"), "Increment"),
+            Invocations { total: 1, succeeded: 0, failed: 1 }
+        );
+        assert_eq!(
+            get(None, "Increment"),
+            Invocations { total: 1, succeeded: 0, failed: 0 }
+        );
+        assert_eq!(upper_camel_case("initialize_v2"), "InitializeV2");
+    }
+}
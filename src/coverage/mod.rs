@@ -2,7 +2,10 @@ use std::collections::HashMap;
 use std::io;
 use std::process::{Command, Stdio};
 use std::time::SystemTime;
-use std::{env, fs, path::PathBuf};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 use clap_serde_derive::{
     clap::{self, ArgAction, ValueEnum},
@@ -12,12 +15,51 @@ use eyre::{bail, Context};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use spinners::{Spinner, Spinners};
+use walkdir::WalkDir;
 
-use crate::{config_parsing::ConfigFileName, from_grcov, util};
+use crate::{config_parsing::ConfigFileName, from_grcov, parsing, util};
 
-mod rustup;
+pub(crate) mod rustup;
 use rustup::is_rustup_managed;
 
+pub(crate) mod workspace;
+use workspace::{package_args, project_dir, Workspace};
+
+pub mod contract_style;
+
+pub mod per_test;
+use per_test::TestMatrix;
+
+pub mod stats;
+
+pub mod summary;
+
+pub mod test_output;
+
+pub mod diff;
+
+pub mod instructions;
+
+pub mod errors;
+
+pub mod exclusions;
+
+pub mod export;
+
+pub mod dispatch;
+
+pub mod functions;
+
+pub mod baseline;
+
+pub mod thresholds;
+
+pub mod report;
+
+pub mod sarif;
+
+pub mod html;
+
 use self::rustup::install_llvm_tools;
 
 // #[derive(Debug, Clone, PartialEq, Parser, Serialize, Deserialize)]
@@ -27,6 +69,31 @@ pub struct Config {
     #[default(".".into())]
     pub path: PathBuf,
 
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Path to the `Cargo.toml` of the project (defaults to `Cargo.toml` in `--path`)"
+    )]
+    #[default(None)]
+    pub manifest_path: Option<PathBuf>,
+
+    #[arg(
+        long = "package",
+        short = 'p',
+        value_name = "SPEC",
+        help = "Workspace member to build, test and report on (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub packages: Vec<String>,
+
+    #[arg(
+        long = "exclude",
+        value_name = "SPEC",
+        help = "Workspace member to exclude from building, testing and reporting (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub exclude: Vec<String>,
+
     #[arg(
         long,
         help = "Version of the compiler toolchain to use (overrides project-specific `rust-toolchain.toml`). Nightly required for branch coverage"
@@ -49,10 +116,74 @@ pub struct Config {
     #[default(false)]
     pub with_sbf: bool,
 
+    #[arg(
+        long = "features",
+        short = 'F',
+        value_name = "FEATURES",
+        value_delimiter = ',',
+        help = "Cargo features to activate (can be stacked or comma separated)"
+    )]
+    #[default(vec![])]
+    pub features: Vec<String>,
+
+    #[arg(
+        long,
+        action(ArgAction::SetTrue),
+        help = "Activate all available cargo features"
+    )]
+    #[default(false)]
+    pub all_features: bool,
+
+    #[arg(
+        long,
+        action(ArgAction::SetTrue),
+        help = "Do not activate the `default` cargo feature"
+    )]
+    #[default(false)]
+    pub no_default_features: bool,
+
+    #[arg(
+        long,
+        value_name = "PROFILE_NAME",
+        help = "Cargo profile to build and test with (e.g. `release`)"
+    )]
+    #[default(None)]
+    pub profile: Option<String>,
+
+    #[arg(
+        long = "cargo-arg",
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Extra argument passed to both `cargo build` and `cargo test` (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub cargo_args: Vec<String>,
+
+    #[arg(
+        long = "rustflag",
+        value_name = "FLAG",
+        allow_hyphen_values = true,
+        help = "Extra flag appended to `RUSTFLAGS`, after the instrumentation flags (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub rustflags: Vec<String>,
+
     #[arg(long, value_enum, help = "Coverage strategy to use")]
     #[default(CoverageStrategy::InstrumentCoverage)]
     pub coverage_strategy: CoverageStrategy,
 
+    #[arg(long, value_enum, help = "Test runner to use")]
+    #[default(TestRunner::CargoTest)]
+    pub test_runner: TestRunner,
+
+    #[arg(
+        long,
+        value_name = "PROFILE_NAME",
+        help = "Nextest profile to run the tests with (requires `--test-runner nextest`)"
+    )]
+    #[default(None)]
+    pub nextest_profile: Option<String>,
+
     // TODO: `-- --exact`?
     #[arg(
         long = "test",
@@ -71,22 +202,26 @@ pub struct Config {
     pub skips: Vec<String>,
 
     #[arg(
-        long = "output-type",
-        value_name = "OUTPUT_TYPE",
-        value_enum,
-        help = "Output type of coverage (can be stacked)"
+        long,
+        alias = "allow-test-failures",
+        action(ArgAction::SetTrue),
+        help = "Still aggregate and report coverage if some of the tests fail"
     )]
-    #[default(vec![OutputType::Html])]
-    pub output_types: Vec<OutputType>,
+    #[default(false)]
+    pub keep_going: bool,
 
     #[arg(
-        long = "contract-style",
-        value_name = "CONTRACT_STYLE",
-        value_enum,
-        help = "Style of contract"
+        long,
+        action(ArgAction::SetTrue),
+        help = "Run each test separately, recording which tests cover which lines and functions (see `zest explain`)"
     )]
-    #[default(ContractStyle::Anchor)]
-    pub contract_style: ContractStyle,
+    #[default(false)]
+    pub per_test: bool,
+
+    #[clap_serde]
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub report: report::Config,
 }
 
 impl ConfigFileName for Config {
@@ -112,6 +247,16 @@ pub enum ContractStyle {
     Native,
 }
 
+impl ContractStyle {
+    /// The functions which count as contract code (for function coverage)
+    pub fn function_query(self) -> &'static tree_sitter::Query {
+        match self {
+            ContractStyle::Anchor => &parsing::PROGRAM_MODULE_QUERY,
+            ContractStyle::Native => &parsing::FUNCTION_QUERY,
+        }
+    }
+}
+
 #[derive(
     Debug,
     Clone,
@@ -124,20 +269,69 @@ pub enum ContractStyle {
     Serialize,
     Deserialize,
 )]
-#[serde(rename_all = "snake_case")]
+// NOTE: kebab-case, same as on the command line (`coveralls-plus`)
+#[serde(rename_all = "kebab-case")]
 pub enum OutputType {
+    /// grcov's HTML report (a file tree)
     #[default]
     Html,
+    /// zest's own HTML report, navigating from the programs to their instructions and sources,
+    /// with a page per analysis
+    ZestHtml,
     Lcov,
+    /// Cobertura XML (e.g. for GitLab merge request widgets)
+    Cobertura,
+    Markdown,
+    /// `covdir` JSON
+    Covdir,
+    /// Coveralls JSON
+    Coveralls,
+    /// Coveralls JSON, with function coverage
+    #[value(alias = "coveralls+")]
+    #[serde(alias = "coveralls+")]
+    CoverallsPlus,
+    /// The paths of the covered files
+    Files,
+    /// ActiveData-ETL JSON
+    Ade,
+    /// zest's own JSON export, organized by program, instruction and function (see [export])
+    Json,
+    /// SARIF log of the never-executed handlers/dispatch arms and never-triggered errors
+    Sarif,
 }
 
-impl From<OutputType> for from_grcov::OutputType {
-    fn from(value: OutputType) -> Self {
-        match value {
-            OutputType::Html => Self::Html,
-            OutputType::Lcov => Self::Lcov,
+impl OutputType {
+    /// Where (under the coverage directory) the output is written
+    pub fn path(
+        self,
+        coverage_dir: &Path,
+    ) -> PathBuf {
+        match self {
+            OutputType::Json => coverage_dir.join(export::FILE_NAME),
+            OutputType::Sarif => coverage_dir.join(sarif::FILE_NAME),
+            OutputType::ZestHtml => coverage_dir.join(html::DIR_NAME),
+            output_type => output_type
+                .to_grcov()
+                .and_then(|output_type| output_type.to_file_name(Some(coverage_dir)))
+                .unwrap_or_else(|| coverage_dir.to_path_buf()),
         }
     }
+
+    /// `None` for the outputs which are not generated by `grcov`
+    pub fn to_grcov(self) -> Option<from_grcov::OutputType> {
+        Some(match self {
+            OutputType::Html => from_grcov::OutputType::Html,
+            OutputType::Lcov => from_grcov::OutputType::Lcov,
+            OutputType::Cobertura => from_grcov::OutputType::Cobertura,
+            OutputType::Markdown => from_grcov::OutputType::Markdown,
+            OutputType::Covdir => from_grcov::OutputType::Covdir,
+            OutputType::Coveralls => from_grcov::OutputType::Coveralls,
+            OutputType::CoverallsPlus => from_grcov::OutputType::CoverallsPlus,
+            OutputType::Files => from_grcov::OutputType::Files,
+            OutputType::Ade => from_grcov::OutputType::Ade,
+            OutputType::ZestHtml | OutputType::Json | OutputType::Sarif => return None,
+        })
+    }
 }
 
 #[derive(
@@ -154,21 +348,81 @@ impl From<OutputType> for from_grcov::OutputType {
 pub enum CoverageStrategy {
     #[default]
     InstrumentCoverage,
-    // BUG: does not currently work
     ZProfile,
 }
 
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionCoverage {
+    /// A contract function is executed if the line of its name has hits
+    #[default]
+    Heuristic,
+    /// The (demangled) function records of the coverage mapping, with generic instantiations,
+    /// closures and trait impl methods, filtered to the contract functions
+    Records,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestRunner {
+    /// `cargo test` (`libtest`)
+    #[default]
+    CargoTest,
+    /// `cargo nextest run`, running each test in its own process
+    Nextest,
+}
+
+impl CoverageStrategy {
+    /// Whether the strategy produces `.profraw` files (source-based coverage) as opposed to
+    /// `.gcno`/`.gcda` files (`gcov`-based coverage)
+    pub fn is_source_based(&self) -> bool {
+        matches!(self, Self::InstrumentCoverage)
+    }
+}
+
 pub fn run(config: Config) -> eyre::Result<()> {
     let Config {
         path,
+        manifest_path,
+        packages,
+        exclude,
         compiler_version,
         branch,
         with_sbf,
+        features,
+        all_features,
+        no_default_features,
+        profile,
+        cargo_args,
+        rustflags,
         coverage_strategy,
+        test_runner,
+        nextest_profile,
         tests,
         skips,
-        output_types,
-        contract_style,
+        keep_going,
+        per_test,
+        report: report_config,
     } = config;
 
     // Check the conditions after parsing
@@ -191,15 +445,64 @@ pub fn run(config: Config) -> eyre::Result<()> {
         eprintln!("Error: The --branch option requires the `compiler_version` to be 'nightly'.");
         std::process::exit(1);
     }
+    let nextest = matches!(test_runner, TestRunner::Nextest);
+    if nextest && with_sbf {
+        eprintln!("Error: The `nextest` test runner cannot be used together with --with-sbf");
+        std::process::exit(1);
+    }
+    if nextest_profile.is_some() && !nextest {
+        eprintln!("Error: The --nextest-profile option requires `--test-runner nextest`");
+        std::process::exit(1);
+    }
+    if per_test && !coverage_strategy.is_source_based() {
+        eprintln!("Error: The --per-test option requires the `instrument-coverage` strategy");
+        std::process::exit(1);
+    }
+    if let Err(err) = report_config.validate(branch) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
 
-    // TODO: use `--manifest-path`
-    env::set_current_dir(&path)
-        .with_context(|| format!("Could not `cd` to `{}`", path.display()))?;
-
-    install_llvm_tools(compiler_version.as_ref())?;
-
-    let target_dir = "./target";
-    let coverage_dir = "./target/coverage";
+    let manifest_path = manifest_path.unwrap_or_else(|| path.join("Cargo.toml"));
+    let manifest_path = fs::canonicalize(&manifest_path)
+        .with_context(|| format!("Could not find {}", manifest_path.display()))?;
+    let project_dir = project_dir(&manifest_path);
+    let workspace =
+        Workspace::from_manifest_path(&manifest_path, compiler_version.as_ref())?;
+    let selected_packages = workspace.select(&packages, &exclude)?;
+    let package_args = package_args(&packages, &exclude);
+
+    // NOTE: shared between `cargo build` and `cargo test`
+    let cargo_args = {
+        let mut args = vec![];
+        if !features.is_empty() {
+            args.extend(["--features".to_string(), features.join(",")]);
+        }
+        if all_features {
+            args.push("--all-features".to_string());
+        }
+        if no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args.extend(cargo_args);
+        args
+    };
+    // NOTE: `cargo nextest run`'s `--profile` is the nextest profile
+    let build_profile_args = profile
+        .iter()
+        .flat_map(|profile| ["--profile", profile])
+        .collect_vec();
+    let test_profile_args = profile
+        .iter()
+        .flat_map(|profile| [if nextest { "--cargo-profile" } else { "--profile" }, profile])
+        .chain(nextest_profile.iter().flat_map(|profile| ["--profile", profile]))
+        .collect_vec();
+
+    install_llvm_tools(compiler_version.as_ref(), project_dir)?;
+
+    let target_dir = workspace.target_dir.as_path();
+    let coverage_dir = target_dir.join("coverage");
+    let coverage_dir = coverage_dir.as_path();
 
     // NOTE: prepare coverage_dir
     {
@@ -210,59 +513,103 @@ pub fn run(config: Config) -> eyre::Result<()> {
             Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
                 eprintln!(
                     "{} already exists, attempting to remove",
-                    coverage_dir
+                    coverage_dir.display()
                 );
                 util::remove_contents(coverage_dir)?;
             }
             Err(err) => {
-                bail!("Could not create {}: {}", coverage_dir, err);
+                bail!("Could not create {}: {}", coverage_dir.display(), err);
             }
         }
 
-        // Clean old `profraw` files
-        // TODO: test if works
-        fs::read_dir(coverage_dir)?
-            .collect::<Result<Vec<_>, _>>()?
-            .iter()
+        // Clean old `profraw` files (including the ones from previous `per_test` runs)
+        WalkDir::new(coverage_dir)
+            .into_iter()
+            .filter_map(Result::ok)
             .filter_map(|entry| {
-                let path = entry.path();
+                let path = entry.into_path();
                 let ext = path.extension()?;
                 util::to_option(ext == "profraw", path)
             })
             .try_for_each(fs::remove_file)?;
+
+        // Clean old outputs which are not necessarily regenerated
+        [
+            test_output::FAILED_TESTS_FILE_NAME,
+            test_output::TEST_LOG_FILE_NAME,
+            TestMatrix::FILE_NAME,
+        ]
+            .iter()
+            .map(|file_name| coverage_dir.join(file_name))
+            .filter(|path| path.exists())
+            .try_for_each(fs::remove_file)?;
+
+        // Clean old `gcda` files, since the counters in them accumulate between runs
+        if !coverage_strategy.is_source_based() && target_dir.exists() {
+            WalkDir::new(target_dir)
+                .into_iter()
+                .filter_map(Result::ok)
+                .map(|entry| entry.into_path())
+                .filter(|path| {
+                    path.extension().map_or(false, |ext| ext == "gcda")
+                })
+                .try_for_each(fs::remove_file)?;
+        }
     }
 
     let mut env_vars: HashMap<&str, String> = HashMap::new();
 
-    // TODO: only for `CoverageStrategy::InstrumentCoverage`
-    env_vars.insert(
-        "LLVM_PROFILE_FILE",
-        format!(
-            "{}/zest-%p-%m.profraw",
-            PathBuf::from(coverage_dir).canonicalize()?.display()
-        ),
-    );
+    let profile_file = |dir: &Path| -> eyre::Result<String> {
+        fs::create_dir_all(dir)?;
+        // NOTE: nextest runs each test in its own process, so instead of a file per process
+        //       the profiles are merged (online) into a small pool of files per binary
+        let pattern = if nextest { "zest-%8m.profraw" } else { "zest-%p-%m.profraw" };
+        Ok(format!("{}/{}", dir.canonicalize()?.display(), pattern))
+    };
+
+    if coverage_strategy.is_source_based() {
+        env_vars.insert("LLVM_PROFILE_FILE", profile_file(coverage_dir)?);
+    }
 
     // NOTE: set compiler env vars
     {
-        // TODO: inherit old `${RUSTFLAGS}`
+        // NOTE: inherited `${RUSTFLAGS}` go first, so that the instrumentation flags (and the
+        //       user-supplied extra flags after them) take precedence
+        let mut all_rustflags: Vec<String> = env::var("RUSTFLAGS")
+            .ok()
+            .into_iter()
+            .filter(|flags| !flags.trim().is_empty())
+            .collect();
+
         match coverage_strategy {
             CoverageStrategy::InstrumentCoverage => {
-                let mut rustflags = "-C instrument-coverage".to_string();
+                all_rustflags.push("-C instrument-coverage".to_string());
 
                 if branch {
-                    rustflags.push_str(" -Z coverage-options=mcdc");
+                    all_rustflags.push("-Z coverage-options=mcdc".to_string());
                 }
-
-                env_vars.insert("RUSTFLAGS", rustflags);
             }
             CoverageStrategy::ZProfile => {
                 // NOTE: "can't instrument with gcov profiling when compiling incrementally"
                 env_vars.insert("CARGO_INCREMENTAL", "0".to_string());
-                env_vars.insert("RUSTFLAGS", "-Z profile".to_string());
+                // NOTE: as recommended by `grcov`, so that the `gcno` files map cleanly
+                //       back to the source lines (no inlining, no dead code elimination)
+                all_rustflags.extend(
+                    [
+                        "-Z profile",
+                        "-C codegen-units=1",
+                        "-C opt-level=0",
+                        "-C link-dead-code",
+                        "-C overflow-checks=off",
+                    ]
+                    .map(String::from),
+                );
             }
         }
 
+        all_rustflags.extend(rustflags);
+        env_vars.insert("RUSTFLAGS", all_rustflags.join(" "));
+
         env_vars.insert("RUST_BACKTRACE", "1".to_string());
         env_vars.insert("RUST_MIN_STACK", "8388608".to_string());
     }
@@ -274,10 +621,20 @@ pub fn run(config: Config) -> eyre::Result<()> {
             Spinner::new(Spinners::Dots, "Building the project...".to_string());
         let cmd = Command::new("cargo")
             .args(compiler_version.as_ref().map(|v| format!("+{}", v)))
-            .args(if with_sbf { ["build-sbf", "--"].iter() } else { ["build"].iter() })
+            .args(if with_sbf { ["build-sbf"].iter() } else { ["build"].iter() })
+            // NOTE: `cargo build-sbf` takes the manifest path itself, the rest is passed to cargo
+            .arg("--manifest-path")
+            .arg(&manifest_path)
+            .args(with_sbf.then_some("--"))
             // NOTE: force color (for prettier error messages)
             .args(["--color", "always"])
-            .args(["--tests", "--target-dir", target_dir])
+            .args(&package_args)
+            .args(&cargo_args)
+            .args(&build_profile_args)
+            .arg("--tests")
+            .arg("--target-dir")
+            .arg(target_dir)
+            .current_dir(project_dir)
             .envs(&env_vars)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -298,44 +655,119 @@ pub fn run(config: Config) -> eyre::Result<()> {
     // Test
     // TODO: limited output
     let _before_tests_time = SystemTime::now();
+    // NOTE: (name, profile directory) of each separately run test, if running `per_test`
+    let mut per_test_runs: Vec<(String, PathBuf)> = vec![];
+    // NOTE: only populated if `keep_going`
+    let mut failed_tests: Vec<String> = vec![];
     {
-        let cargo_test = |tests: Option<&String>| -> eyre::Result<()> {
-            let tests_signifier = tests
-                .as_ref()
-                .map(|test| format!(" ({})", test))
-                .unwrap_or_default();
+        let test_command = |subcommand: &str, filters: &[&String]| -> Command {
+            let mut cmd = Command::new("cargo");
+            cmd.args(compiler_version.as_ref().map(|v| format!("+{}", v)));
+            if with_sbf {
+                // NOTE: `cargo test-sbf` takes the manifest path itself, the rest is passed to
+                //       cargo
+                cmd.args(["test-sbf", "--manifest-path"])
+                    .arg(&manifest_path)
+                    .arg("--");
+            } else if nextest {
+                cmd.args(["nextest", subcommand]);
+            } else {
+                cmd.arg("test");
+            }
+            if !with_sbf {
+                cmd.arg("--manifest-path").arg(&manifest_path);
+            }
+            cmd
+                // NOTE: force color (for prettier error messages), except for nextest, whose
+                //       status lines are parsed for the failed tests
+                .args(["--color", if nextest { "never" } else { "always" }])
+                .args(&package_args)
+                .args(&cargo_args)
+                .args(&test_profile_args)
+                // NOTE: no filter is passed if empty
+                .args(filters)
+                .arg("--target-dir")
+                .arg(target_dir)
+                .current_dir(project_dir);
+            cmd
+        };
+
+        let cargo_test = |filters: &[&String],
+                          exact: bool,
+                          env_vars: &HashMap<&str, String>|
+         -> eyre::Result<Vec<String>> {
+            let tests_signifier = if filters.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", filters.iter().join(", "))
+            };
 
             let mut spinner = Spinner::new(
                 Spinners::Dots,
                 format!("Running the tests{}...", tests_signifier),
             );
 
-            let cmd = Command::new("cargo")
-                .args(compiler_version.as_ref().map(|v| format!("+{}", v)))
-                .args(if with_sbf { ["test-sbf", "--"].iter() } else { ["test"].iter() })
-                // NOTE: force color (for prettier error messages)
-                .args(["--color", "always"])
-                // NOTE: no filter is passed if `None`
-                .args(tests)
-                .args(["--target-dir", target_dir])
+            let mut cmd = test_command("run", filters);
+            // NOTE: the output of passing tests is also kept, for the program logs
+            if nextest {
+                cmd.args(["--success-output", "immediate", "--failure-output", "immediate"]);
+            }
+            // NOTE: nextest emulates `libtest`'s `--exact` and `--skip`
+            let cmd = cmd
+                // NOTE: otherwise the remaining test binaries aren't run after a failure
+                .args(keep_going.then_some("--no-fail-fast"))
                 .arg("--")
+                .args((!nextest).then_some("--show-output"))
+                .args(exact.then_some("--exact"))
                 .args(skips.iter().flat_map(|skip| ["--skip", skip]))
-                .envs(&env_vars)
+                .envs(env_vars)
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
                 .spawn()?;
 
             let output = cmd.wait_with_output()?;
+            test_output::append_test_log(
+                coverage_dir.join(test_output::TEST_LOG_FILE_NAME),
+                &output.stdout,
+                &output.stderr,
+            )?;
+            if !output.status.success() && keep_going {
+                spinner.stop_and_persist(
+                    "❌",
+                    format!("Tests{} failed, continuing...", tests_signifier),
+                );
+
+                let mut failed_tests = if nextest {
+                    test_output::parse_failed_nextest_tests(std::str::from_utf8(
+                        &output.stderr,
+                    )?)
+                } else {
+                    test_output::parse_failed_tests(std::str::from_utf8(
+                        &output.stdout,
+                    )?)
+                };
+                // NOTE: e.g. a test binary crashed instead of reporting a failure
+                if failed_tests.is_empty() {
+                    failed_tests.push(if filters.is_empty() {
+                        "(all tests)".to_string()
+                    } else {
+                        filters.iter().join(", ")
+                    });
+                }
+
+                return Ok(failed_tests);
+            }
             if !output.status.success() {
                 spinner.stop_and_persist(
                     "❌",
                     format!("Tests{} failed!", tests_signifier),
                 );
-                eprintln!("cargo test stdout:");
+                let runner = if nextest { "cargo nextest run" } else { "cargo test" };
+                eprintln!("{} stdout:", runner);
                 eprintln!("{}", std::str::from_utf8(&output.stdout)?);
-                eprintln!("cargo test stderr:");
+                eprintln!("{} stderr:", runner);
                 eprintln!("{}", std::str::from_utf8(&output.stderr)?);
-                bail!("`cargo test` failed");
+                bail!("`{}` failed", runner);
             }
 
             spinner.stop_and_persist(
@@ -343,149 +775,89 @@ pub fn run(config: Config) -> eyre::Result<()> {
                 format!("Tests{} finished!", tests_signifier),
             );
 
-            Ok(())
+            Ok(vec![])
         };
 
-        // NOTE: cargo does not support providing multiple ranges
-        if tests.is_empty() {
-            // NOTE: run all tests
-            cargo_test(None)?;
+        if per_test {
+            // NOTE: listing the tests also runs the (instrumented) test binaries,
+            //       their profile data is thrown away afterwards
+            let listing_dir = coverage_dir.join("listing");
+            let mut listing_env_vars = env_vars.clone();
+            listing_env_vars
+                .insert("LLVM_PROFILE_FILE", profile_file(&listing_dir)?);
+
+            let list_tests = |filters: &[&String]| -> eyre::Result<Vec<String>> {
+                let mut cmd = test_command("list", filters);
+                if nextest {
+                    cmd.args(["--message-format", "json", "--"]);
+                } else {
+                    cmd.args(["--", "--list", "--format", "terse"]);
+                }
+                let output = cmd
+                    .args(skips.iter().flat_map(|skip| ["--skip", skip]))
+                    .envs(&listing_env_vars)
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?
+                    .wait_with_output()?;
+                if !output.status.success() {
+                    eprintln!("cargo test stderr:");
+                    eprintln!("{}", std::str::from_utf8(&output.stderr)?);
+                    bail!("Listing the tests failed");
+                }
+
+                let stdout = std::str::from_utf8(&output.stdout)?;
+                if nextest {
+                    per_test::parse_nextest_list(stdout)
+                } else {
+                    Ok(per_test::parse_test_list(stdout))
+                }
+            };
+
+            let test_names = if tests.is_empty() || nextest {
+                // NOTE: nextest accepts multiple filters at once
+                list_tests(&tests.iter().collect_vec())?
+            } else {
+                tests
+                    .iter()
+                    .map(|test| list_tests(&[test]))
+                    .flatten_ok()
+                    .collect::<eyre::Result<Vec<_>>>()?
+            };
+            util::remove_contents(&listing_dir)?;
+
+            // NOTE: same-named tests from different test binaries are run (and attributed) together
+            for (index, test_name) in test_names.into_iter().unique().enumerate() {
+                let profile_dir = coverage_dir.join("per_test").join(index.to_string());
+                let mut test_env_vars = env_vars.clone();
+                test_env_vars.insert("LLVM_PROFILE_FILE", profile_file(&profile_dir)?);
+
+                failed_tests.extend(cargo_test(&[&test_name], true, &test_env_vars)?);
+                per_test_runs.push((test_name, profile_dir));
+            }
+        } else if tests.is_empty() || nextest {
+            // NOTE: cargo test does not support providing multiple filters, nextest does
+            failed_tests.extend(cargo_test(&tests.iter().collect_vec(), false, &env_vars)?);
         } else {
             // NOTE: run selected tests
-            tests.iter().map(Some).try_for_each(cargo_test)?;
+            for test in &tests {
+                failed_tests.extend(cargo_test(&[test], false, &env_vars)?);
+            }
         }
     }
     let _after_tests_time = SystemTime::now();
 
     // TODO: `{before,after}-test` shenanigans/optimizations
 
-    // NOTE: run grcov
-    {
-        let mut spinner = Spinner::new(
-            Spinners::Dots,
-            "Aggregating coverage info...".to_string(),
-        );
-        // NOTE: for filtering out "irrelevant" lines, i.e. only leaving the contract code
-        let re = regex::Regex::new(
-            r#"(?x)
-            ^\s*\#\[(program|account)\]$       # Matches #[program] or #[account]
-            |
-            ^\s*\#\[(tokio::)?test\]$          # Matches #[test] or #[tokio::test]
-            |
-            ^\s*\#\[derive\(\s*[^\)]+\s*\)\]$  # Matches #[derive(Trait, ...)]
-            |
-            ^\s*declare_id!\(\s*.*\s*\);$      # Matches declare_id!(...)
-            |
-            ^\s*declare_program!\(\s*.*\s*\);$ # Matches declare_id!(...)
-            # |
-            # ^\s*$                              # Matches "empty" lines
-            # |
-            # ^\s*[\(\)\[\]\{\}]*\s*$            # Matches lines with only brackets
-        "#,
-        )?;
-
-        // NOTE: extrapolated from running the original `grcov` CLI with appropriate arguments
-        let opt = from_grcov::Opt {
-            paths: vec![coverage_dir.to_string()],
-            binary_path: Some(target_dir.into()),
-            llvm_path: None,
-            // NOTE: `create::OutputType` `into()`-es to `crate::from_grcov::OutputType`
-            output_types: output_types
-                .iter()
-                .cloned()
-                .map(std::convert::Into::into)
-                .collect(),
-            output_path: Some(coverage_dir.into()),
-            output_config_file: None,
-            // NOTE: `.` because we `cd`-ed into the correct directory already
-            source_dir: Some(".".into()),
-            prefix_dir: None,
-            ignore_not_existing: true,
-            // NOTE: parsed as globs, see [globset::Globset]
-            ignore_dir: vec!["target/*".to_string(), "*tests*".to_string()],
-            keep_dir: vec![],
-            path_mapping: None,
+    report::run(
+        report_config,
+        report::Context {
+            workspace: &workspace,
+            selected_packages,
+            coverage_strategy,
             branch,
-            filter: None,
-            // NOTE: only sorting for `Html`, `LCov` users can sort themselves
-            sort_output_types: vec![from_grcov::OutputType::Html],
-            llvm: true,
-            token: None,
-            commit_sha: None,
-            service_name: None,
-            service_number: None,
-            service_job_id: None,
-            service_pull_request: None,
-            service_flag_name: None,
-            parallel: false,
-            threads: None,
-            precision: 2,
-            guess_directory: false,
-            vcs_branch: "master".to_string(),
-            log: PathBuf::from("stderr"),
-            log_level: from_grcov::LevelFilterArg(log::LevelFilter::Error),
-            excl_line: Some(re),
-            excl_start: None,
-            excl_stop: None,
-            excl_br_line: None,
-            excl_br_start: None,
-            excl_br_stop: None,
-            no_demangle: false,
-            contract_style,
-        };
-
-        from_grcov::main(opt)?;
-        spinner.stop_and_persist("✅", "Coverage aggregated!".to_string());
-    }
-
-    // NOTE: experimentation with `tarpaulin` as a backend
-    //       `branch_coverage` is stubbed, not useful to us
-    // {
-    //     // `cargo tarpaulin --skip-clean --out Html --engine Llvm --output-dir target/coverage`
-    //     let mut config: tarpaulin::config::Config = Default::default();
-    //     config.command = tarpaulin::config::Mode::Test;
-    //     config.set_clean(false);
-    //     config.generate = vec![tarpaulin::config::OutputFile::Html];
-    //     config.set_engine(tarpaulin::config::TraceEngine::Llvm);
-    //     config.branch_coverage = branch;
-    //     config.output_directory = Some("./target/coverage/".into());
-    //     config.test_timeout = std::time::Duration::from_secs(120);
-    //     config.exclude_path(&PathBuf::from("*tests*"));
-    //
-    //     tarpaulin::run(&[config]);
-    // }
-
-    // NOTE: run grcov (old way, through CLI)
-    //       depends on CLI, tradeoffs
-    // {
-    //     // grcov program/coverage --llvm -t html -o target/coverage
-    //     let mut cmd = Command::new("grcov")
-    //         .args([program_DIR, "--llvm", "-t", "html", "-o", program_DIR])
-    //         .spawn()?;
-    //
-    //     cmd.wait()?;
-    // }
-
-    // NOTE: Report regenerated outputs (and possibly open, if applicable)
-    output_types.iter().unique().try_for_each(|output_type| {
-        match output_type {
-            OutputType::Html => {
-                eprintln!(
-                    "Successfully generated html report at {}, opening...",
-                    path.join("target/coverage/html/index.html").display(),
-                );
-                // open::that("./target/coverage/tarpaulin-report.html")
-                open::that("./target/coverage/html/index.html")
-            }
-            OutputType::Lcov => {
-                eprintln!(
-                    "Successfully generated lcov report, you can find it at {}",
-                    path.join("target/coverage/lcov").display(),
-                );
-                Ok(())
-            }
-        }
-    })?;
-
-    Ok(())
+            per_test_runs,
+            failed_tests,
+        },
+    )
 }
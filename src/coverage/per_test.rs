@@ -0,0 +1,344 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use grcov::ResultTuple;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::from_grcov;
+
+/// Which tests hit which lines/functions, stored as indices into `tests`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TestMatrix {
+    pub tests: Vec<String>,
+    /// Keyed by the path relative to the project root
+    pub files: BTreeMap<PathBuf, FileMatrix>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileMatrix {
+    pub lines: BTreeMap<u32, Vec<usize>>,
+    pub functions: BTreeMap<String, Vec<usize>>,
+}
+
+impl TestMatrix {
+    pub const FILE_NAME: &'static str = "per_test.json";
+    pub const HTML_FILE_NAME: &'static str = "per_test.html";
+
+    /// Aggregate each test's profile data separately (`opt` is reused, modulo the `paths`)
+    pub fn from_runs(
+        opt: &from_grcov::Opt,
+        runs: &[(String, PathBuf)],
+    ) -> eyre::Result<Self> {
+        let mut matrix = Self::default();
+
+        for (test, profile_dir) in runs {
+            let opt = from_grcov::Opt {
+                paths: vec![profile_dir.display().to_string()],
+                ..opt.clone()
+            };
+            let (results, _) = from_grcov::aggregate(&opt)?;
+            matrix.add(test.clone(), &results);
+        }
+
+        Ok(matrix)
+    }
+
+    pub fn add(
+        &mut self,
+        test: String,
+        results: &[ResultTuple],
+    ) {
+        let index = self.tests.len();
+        self.tests.push(test);
+
+        for (_, rel_path, result) in results {
+            let file = self.files.entry(rel_path.clone()).or_default();
+
+            result
+                .lines
+                .iter()
+                .filter(|(_, hits)| **hits > 0)
+                .for_each(|(line, _)| {
+                    file.lines.entry(*line).or_default().push(index)
+                });
+
+            result
+                .functions
+                .iter()
+                .filter(|(_, function)| function.executed)
+                .for_each(|(name, _)| {
+                    file.functions.entry(name.clone()).or_default().push(index)
+                });
+        }
+    }
+
+    pub fn tests_covering_line(
+        &self,
+        file: &Path,
+        line: u32,
+    ) -> Vec<&str> {
+        self.resolve(
+            self.files.get(file).and_then(|file| file.lines.get(&line)),
+        )
+    }
+
+    /// `function` can be either the qualified name (e.g. `processor::Processor::process`) or
+    /// any of its trailing segments (e.g. `process`), in which case all of the matching
+    /// functions are taken into account
+    pub fn tests_covering_function(
+        &self,
+        file: &Path,
+        function: &str,
+    ) -> Vec<&str> {
+        let Some(file) = self.files.get(file) else {
+            return vec![];
+        };
+        if let Some(indices) = file.functions.get(function) {
+            return self.resolve(Some(indices));
+        }
+
+        let suffix = format!("::{}", function);
+        let indices = file
+            .functions
+            .iter()
+            .filter(|(name, _)| name.ends_with(&suffix))
+            .flat_map(|(_, indices)| indices.iter().copied())
+            .sorted()
+            .dedup()
+            .collect::<Vec<_>>();
+        self.resolve(Some(&indices))
+    }
+
+    fn resolve(
+        &self,
+        indices: Option<&Vec<usize>>,
+    ) -> Vec<&str> {
+        indices
+            .into_iter()
+            .flatten()
+            .map(|index| self.tests[*index].as_str())
+            .collect()
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).with_context(|| {
+            format!(
+                "No per-test coverage data found at {} (run `zest coverage --per-test` first)",
+                path.display()
+            )
+        })?;
+
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Could not parse {}", path.display()))
+    }
+
+    pub fn write(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> eyre::Result<()> {
+        let path = path.as_ref();
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Could not write {}", path.display()))
+    }
+
+    /// For the per-test page of the HTML report, listing the tests which cover each line and
+    /// function
+    pub fn html_context(
+        &self,
+        source_root: &Path,
+    ) -> tera::Context {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            number: u32,
+            source: &'a str,
+            tests: Vec<&'a str>,
+        }
+
+        #[derive(Serialize)]
+        struct File<'a> {
+            path: String,
+            functions: Vec<(&'a str, Vec<&'a str>)>,
+            lines: Vec<Line<'a>>,
+        }
+
+        let sources = self
+            .files
+            .keys()
+            .map(|rel_path| {
+                fs::read_to_string(source_root.join(rel_path))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let files = self
+            .files
+            .iter()
+            .zip(&sources)
+            .filter(|((_, file), _)| !file.lines.is_empty())
+            .map(|((rel_path, file), source)| {
+                let source_lines = source.lines().collect::<Vec<_>>();
+
+                File {
+                    path: rel_path.display().to_string(),
+                    functions: file
+                        .functions
+                        .iter()
+                        .map(|(name, indices)| {
+                            (name.as_str(), self.resolve(Some(indices)))
+                        })
+                        .collect(),
+                    lines: file
+                        .lines
+                        .iter()
+                        .map(|(number, indices)| Line {
+                            number: *number,
+                            // NOTE: `CovResult`'s `lines` are 1-indexed
+                            source: source_lines
+                                .get(*number as usize - 1)
+                                .copied()
+                                .unwrap_or_default(),
+                            tests: self.resolve(Some(indices)),
+                        })
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut context = tera::Context::new();
+        context.insert("tests", &self.tests);
+        context.insert("files", &files);
+        context
+    }
+}
+
+/// Parse the output of `cargo test -- --list --format terse`
+pub fn parse_test_list(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_suffix(": test"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse the output of `cargo nextest list --message-format json`
+pub fn parse_nextest_list(stdout: &str) -> eyre::Result<Vec<String>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct TestList {
+        rust_suites: BTreeMap<String, Suite>,
+    }
+
+    #[derive(Deserialize)]
+    struct Suite {
+        testcases: BTreeMap<String, TestCase>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct TestCase {
+        filter_match: FilterMatch,
+    }
+
+    #[derive(Deserialize)]
+    struct FilterMatch {
+        status: String,
+    }
+
+    let list: TestList = serde_json::from_str(stdout)
+        .context("Could not parse the `cargo nextest list` output")?;
+
+    Ok(list
+        .rust_suites
+        .into_values()
+        .flat_map(|suite| suite.testcases)
+        // NOTE: tests not matching the filters (and ignored ones) are still listed
+        .filter(|(_, test_case)| test_case.filter_match.status == "matches")
+        .map(|(name, _)| name)
+        .collect())
+}
+
+#[rustfmt::skip]
+pub const HTML_TEMPLATE: &str = /* html */ r##"
+{% extends "base.html" %}
+{% block title %}per-test coverage{% endblock title %}
+{% block content %}
+  <h1>Per-test coverage</h1>
+  <p>{{ tests | length }} test(s) were run separately</p>
+  {% for file in files %}
+  <h2><a href="{{ file.path | source_page }}">{{ file.path }}</a></h2>
+  {% if file.functions %}
+  <table>
+    <tr><th>Function</th><th>Executed by</th></tr>
+    {% for function in file.functions %}
+    <tr>
+      <td class="source">{{ function.0 }}</td>
+      <td><ul>{% for test in function.1 %}<li>{{ test }}</li>{% endfor %}</ul></td>
+    </tr>
+    {% endfor %}
+  </table>
+  {% endif %}
+  <table>
+    <tr><th>Line</th><th>Source</th><th>Covered by</th></tr>
+    {% for line in file.lines %}
+    <tr>
+      <td class="number"><a href="{{ file.path | source_page }}#L{{ line.number }}">{{ line.number }}</a></td>
+      <td class="source">{{ line.source }}</td>
+      <td><ul>{% for test in line.tests %}<li>{{ test }}</li>{% endfor %}</ul></td>
+    </tr>
+    {% endfor %}
+  </table>
+  {% endfor %}
+{% endblock content %}
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_test_list() {
+        let stdout = "\
+tests::test_increment: test
+tests::test_initialize: test
+benches::bench_increment: bench
+";
+
+        assert_eq!(
+            parse_test_list(stdout),
+            vec!["tests::test_increment", "tests::test_initialize"]
+        );
+    }
+
+    #[test]
+    fn parses_nextest_list() {
+        let stdout = r#"{
+  "test-count": 3,
+  "rust-suites": {
+    "counter": {
+      "binary-id": "counter",
+      "testcases": {
+        "tests::test_increment": { "ignored": false, "filter-match": { "status": "matches" } },
+        "tests::test_slow": { "ignored": true, "filter-match": { "status": "mismatch", "reason": "ignored" } }
+      }
+    },
+    "counter::integration": {
+      "binary-id": "counter::integration",
+      "testcases": {
+        "test_initialize": { "ignored": false, "filter-match": { "status": "matches" } }
+      }
+    }
+  }
+}"#;
+
+        assert_eq!(
+            parse_nextest_list(stdout).unwrap(),
+            vec!["tests::test_increment", "test_initialize"]
+        );
+    }
+}
@@ -0,0 +1,705 @@
+use std::{fs, path::PathBuf};
+
+use clap_serde_derive::{
+    clap::{self, ArgAction, ValueEnum},
+    ClapSerde,
+};
+use eyre::bail;
+use itertools::Itertools;
+use spinners::{Spinner, Spinners};
+
+use crate::from_grcov;
+
+use super::{
+    baseline::{self, Baseline},
+    contract_style::ContractStyles,
+    diff::{self, DiffCoverage},
+    dispatch::DispatchCoverage,
+    errors::{self, CustomErrorCoverage},
+    exclusions::Exclusions,
+    export::{Analyses, Export},
+    functions::FunctionSpanCoverage,
+    html,
+    instructions::{self, InstructionCoverage},
+    per_test::TestMatrix,
+    sarif,
+    summary::{ColorThresholds, Summary, SummaryLevel, SummarySort},
+    test_output,
+    thresholds::{self, Threshold},
+    workspace::{Package, Workspace},
+    ContractStyle, CoverageStrategy, FunctionCoverage, OutputType,
+};
+
+/// Options for aggregating the coverage data and generating the reports
+#[derive(ClapSerde, Debug, Clone)]
+// NOTE: clap names the argument group after the (generated) struct, which would collide with
+// the `Config` it is flattened into
+#[group(id = "report")]
+pub struct Config {
+    #[arg(
+        long = "output-type",
+        value_name = "OUTPUT_TYPE",
+        value_enum,
+        help = "Output type of coverage (can be stacked)"
+    )]
+    #[default(vec![OutputType::Html])]
+    pub output_types: Vec<OutputType>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory with templates replacing (or adding to) the built-in ones of the `zest-html` report (e.g. `base.html`, `index.html`, `program.html`, `source.html`)"
+    )]
+    #[default(None)]
+    pub html_templates: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "Fail if the total line coverage is below PERCENT"
+    )]
+    #[default(None)]
+    pub fail_under_lines: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "Fail if the total function coverage is below PERCENT"
+    )]
+    #[default(None)]
+    pub fail_under_functions: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "Fail if the total branch coverage is below PERCENT (requires `--branch`)"
+    )]
+    #[default(None)]
+    pub fail_under_branches: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "REV",
+        help = "Also report coverage of only the lines added or modified since the git revision REV (including uncommitted changes and untracked files)"
+    )]
+    #[default(None)]
+    pub diff_base: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "Fail if the coverage of the changed lines is below PERCENT (requires `--diff-base`)"
+    )]
+    #[default(None)]
+    pub fail_under_diff: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save a per-file/per-function coverage summary (JSON) to FILE"
+    )]
+    #[default(None)]
+    pub save_baseline: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Report coverage regressions compared to a summary saved with `--save-baseline`"
+    )]
+    #[default(None)]
+    pub compare_baseline: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action(ArgAction::SetTrue),
+        help = "Fail if there are any coverage regressions (requires `--compare-baseline`)"
+    )]
+    #[default(false)]
+    pub fail_on_regression: bool,
+
+    /// Per-file/per-package thresholds (config file only)
+    #[arg(skip)]
+    #[default(vec![])]
+    pub thresholds: Vec<Threshold>,
+
+    #[arg(
+        long = "contract-style",
+        value_name = "CONTRACT_STYLE",
+        value_enum,
+        help = "Style of contract of all crates (detected per crate from its dependencies and the `Anchor.toml` by default)"
+    )]
+    #[default(None)]
+    pub contract_style: Option<ContractStyle>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Where the function coverage comes from"
+    )]
+    #[default(FunctionCoverage::Heuristic)]
+    pub function_coverage: FunctionCoverage,
+
+    #[arg(
+        long = "ignore",
+        value_name = "GLOB",
+        help = "Also ignore files matching GLOB (relative to the project root) in the reports (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub ignore: Vec<String>,
+
+    #[arg(
+        long = "default-ignore",
+        value_name = "GLOB",
+        help = "Files always ignored in the reports, `target/*` and `*tests*` by default (can be stacked, replaces the defaults)"
+    )]
+    #[default(vec!["target/*".to_string(), "*tests*".to_string()])]
+    pub default_ignore: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        value_enum,
+        help = "Print a coverage summary table per program, file or function"
+    )]
+    #[default(SummaryLevel::Program)]
+    pub summary: SummaryLevel,
+
+    #[arg(
+        long,
+        value_name = "METRIC",
+        value_enum,
+        help = "Sort the summary table by name or (least covered first) by a metric"
+    )]
+    #[default(SummarySort::Name)]
+    pub summary_sort: SummarySort,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Only list the N least covered rows in the summary table (by `--summary-sort`, lines if sorting by name)"
+    )]
+    #[default(None)]
+    pub summary_top: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "Color the summary percentages below PERCENT red"
+    )]
+    #[default(50.0)]
+    pub summary_low: f64,
+
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        help = "Color the summary percentages below PERCENT yellow (and the rest green)"
+    )]
+    #[default(80.0)]
+    pub summary_high: f64,
+
+    /// User-defined line regexes, start/stop markers and tree-sitter queries (config file only)
+    #[arg(skip)]
+    #[default(Exclusions::default())]
+    pub exclusions: Exclusions,
+}
+
+impl Config {
+    /// Check the conditions which don't depend on the build and test stages (whose `branch`
+    /// setting is given), so that they fail before running them
+    pub fn validate(
+        &self,
+        branch: bool,
+    ) -> Result<(), String> {
+        if self.fail_under_diff.is_some() && self.diff_base.is_none() {
+            return Err("The --fail-under-diff option requires --diff-base".to_string());
+        }
+        if self.fail_on_regression && self.compare_baseline.is_none() {
+            return Err("The --fail-on-regression option requires --compare-baseline".to_string());
+        }
+        // NOTE: without branch coverage there are no branches, which can't be below anything
+        if !branch {
+            if self.fail_under_branches.is_some() {
+                return Err("The --fail-under-branches option requires --branch".to_string());
+            }
+            if self.thresholds.iter().any(|threshold| threshold.branches.is_some()) {
+                return Err("Thresholds on `branches` require --branch".to_string());
+            }
+        }
+
+        let percentages = [
+            ("--fail-under-lines", self.fail_under_lines),
+            ("--fail-under-functions", self.fail_under_functions),
+            ("--fail-under-branches", self.fail_under_branches),
+            ("--fail-under-diff", self.fail_under_diff),
+        ]
+        .into_iter()
+        .chain(self.thresholds.iter().flat_map(|threshold| {
+            [
+                ("The `lines` threshold", threshold.lines),
+                ("The `functions` threshold", threshold.functions),
+                ("The `branches` threshold", threshold.branches),
+            ]
+        }));
+        for (name, percent) in percentages {
+            if let Some(percent) = percent.filter(|percent| !(0.0..=100.0).contains(percent)) {
+                return Err(format!("{} must be between 0 and 100, got {}", name, percent));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the report stage needs to know about the (already finished) build and test stages
+pub struct Context<'a> {
+    pub workspace: &'a Workspace,
+    /// `None` if no workspace members were explicitly selected
+    pub selected_packages: Option<Vec<&'a Package>>,
+    pub coverage_strategy: CoverageStrategy,
+    pub branch: bool,
+    /// (name, profile directory) of each separately run test, empty if not running `per_test`
+    pub per_test_runs: Vec<(String, PathBuf)>,
+    pub failed_tests: Vec<String>,
+}
+
+/// The `grcov` options for aggregating the profile data of `context` (and writing out the
+/// `output_types`)
+pub fn grcov_opt(
+    config: &Config,
+    context: &Context,
+) -> eyre::Result<from_grcov::Opt> {
+    let Config {
+        output_types,
+        contract_style,
+        function_coverage,
+        ignore,
+        default_ignore,
+        exclusions,
+        ..
+    } = config;
+    let Context {
+        workspace,
+        selected_packages,
+        coverage_strategy,
+        branch,
+        ..
+    } = context;
+
+    let target_dir = workspace.target_dir.as_path();
+    let coverage_dir = target_dir.join("coverage");
+    let coverage_dir = coverage_dir.as_path();
+
+    let contract_styles = match contract_style {
+        Some(contract_style) => ContractStyles::uniform(*contract_style),
+        None => ContractStyles::detect_all(workspace)?,
+    };
+
+    // NOTE: for filtering out "irrelevant" lines, i.e. only leaving the contract code
+    let excl_line = exclusions.line_regex()?;
+    let (excl_start, excl_stop) = exclusions.marker_regexes()?;
+
+    // NOTE: extrapolated from running the original `grcov` CLI with appropriate arguments
+    // NOTE: `.profraw` files are gathered in `coverage_dir`, while the `.gcno`/`.gcda` files
+    //       are written next to the object files (somewhere in `target_dir`)
+    let (paths, binary_path) = if coverage_strategy.is_source_based() {
+        (
+            vec![coverage_dir.display().to_string()],
+            Some(target_dir.into()),
+        )
+    } else {
+        (vec![target_dir.display().to_string()], None)
+    };
+
+    // NOTE: only report on the selected workspace members (if any were selected),
+    //       globs are matched against paths relative to the `source_dir`
+    let keep_dir = selected_packages
+        .iter()
+        .flatten()
+        .map(|package| {
+            let rel_dir = package
+                .dir()
+                .strip_prefix(&workspace.root)
+                .unwrap_or(package.dir());
+            if rel_dir.as_os_str().is_empty() {
+                "*".to_string()
+            } else {
+                format!("{}/*", rel_dir.display())
+            }
+        })
+        .collect();
+
+    let opt = from_grcov::Opt {
+        paths,
+        binary_path,
+        llvm_path: None,
+        // NOTE: only the ones generated by `grcov`, the rest are written by `zest` itself
+        output_types: output_types
+            .iter()
+            .filter_map(|output_type| output_type.to_grcov())
+            .collect(),
+        output_path: Some(coverage_dir.into()),
+        output_config_file: None,
+        source_dir: Some(workspace.root.clone()),
+        prefix_dir: None,
+        ignore_not_existing: true,
+        // NOTE: parsed as globs, see [globset::Globset]
+        ignore_dir: default_ignore.iter().chain(ignore).cloned().collect(),
+        keep_dir,
+        path_mapping: None,
+        branch: *branch,
+        filter: None,
+        // NOTE: only sorting for `Html`, `LCov` users can sort themselves
+        sort_output_types: vec![from_grcov::OutputType::Html],
+        // NOTE: `gcno` files are still checked for being produced by `LLVM` when `false`
+        llvm: coverage_strategy.is_source_based(),
+        token: None,
+        commit_sha: None,
+        service_name: None,
+        service_number: None,
+        service_job_id: None,
+        service_pull_request: None,
+        service_flag_name: None,
+        parallel: false,
+        threads: None,
+        precision: 2,
+        guess_directory: false,
+        vcs_branch: "master".to_string(),
+        log: PathBuf::from("stderr"),
+        log_level: from_grcov::LevelFilterArg(log::LevelFilter::Error),
+        excl_line: Some(excl_line),
+        excl_start,
+        excl_stop,
+        excl_br_line: None,
+        excl_br_start: None,
+        excl_br_stop: None,
+        no_demangle: false,
+        contract_styles,
+        function_coverage: *function_coverage,
+        excl_queries: exclusions.queries.clone(),
+    };
+
+    Ok(opt)
+}
+
+pub fn run(
+    config: Config,
+    context: Context,
+) -> eyre::Result<()> {
+    let Config {
+        output_types,
+        html_templates,
+        fail_under_lines,
+        fail_under_functions,
+        fail_under_branches,
+        diff_base,
+        fail_under_diff,
+        save_baseline,
+        compare_baseline,
+        fail_on_regression,
+        thresholds,
+        summary,
+        summary_sort,
+        summary_top,
+        summary_low,
+        summary_high,
+        ..
+    } = config.clone();
+
+    let opt = grcov_opt(&config, &context)?;
+    // NOTE: execution counts are only known from the function records
+    let with_execution_counts = matches!(opt.function_coverage, FunctionCoverage::Records);
+    let contract_styles = opt.contract_styles.clone();
+
+    let Context {
+        workspace,
+        branch,
+        per_test_runs,
+        failed_tests,
+        ..
+    } = context;
+
+    let target_dir = workspace.target_dir.as_path();
+    let coverage_dir = target_dir.join("coverage");
+    let coverage_dir = coverage_dir.as_path();
+
+    // NOTE: run grcov
+    let (results, execution_counts, test_matrix) = {
+        let mut spinner = Spinner::new(
+            Spinners::Dots,
+            "Aggregating coverage info...".to_string(),
+        );
+
+        // NOTE: has to be done before `opt` is consumed
+        let test_matrix = if !per_test_runs.is_empty() {
+            Some(TestMatrix::from_runs(&opt, &per_test_runs)?)
+        } else {
+            None
+        };
+
+        let (results, execution_counts) = from_grcov::main(opt)?;
+
+        if let Some(test_matrix) = &test_matrix {
+            test_matrix.write(coverage_dir.join(TestMatrix::FILE_NAME))?;
+        }
+
+        spinner.stop_and_persist("✅", "Coverage aggregated!".to_string());
+
+        (results, execution_counts, test_matrix)
+    };
+
+    if !failed_tests.is_empty() {
+        test_output::write_failed_tests(
+            &failed_tests,
+            coverage_dir.join(test_output::FAILED_TESTS_FILE_NAME),
+        )?;
+    }
+
+    let diff_coverage = diff_base
+        .map(|base| -> eyre::Result<_> {
+            let changed_lines = diff::changed_lines(&workspace.root, &base)?;
+            Ok(DiffCoverage::new(&base, &results, &changed_lines))
+        })
+        .transpose()?;
+
+    // NOTE: the (program) logs of the tests
+    let test_log = {
+        let path = coverage_dir.join(test_output::TEST_LOG_FILE_NAME);
+        if path.exists() {
+            String::from_utf8_lossy(&fs::read(path)?).into_owned()
+        } else {
+            String::new()
+        }
+    };
+
+    let invocation_log = instructions::parse_invocations(&test_log);
+    let instruction_coverage =
+        InstructionCoverage::new(&results, workspace, &contract_styles, &invocation_log)?;
+    let dispatch_coverage = DispatchCoverage::new(&results, workspace, &contract_styles)?;
+    let function_span_coverage = FunctionSpanCoverage::new(
+        &results,
+        workspace,
+        &contract_styles,
+        with_execution_counts.then_some(&execution_counts),
+    )?;
+    let error_log = errors::parse_custom_errors(&test_log);
+    let error_coverage = CustomErrorCoverage::new(&results, workspace, &error_log)?;
+    let analyses = Analyses {
+        instruction_coverage: &instruction_coverage,
+        dispatch_coverage: &dispatch_coverage,
+        function_span_coverage: &function_span_coverage,
+        error_coverage: &error_coverage,
+    };
+
+    if output_types.contains(&OutputType::Json) || output_types.contains(&OutputType::ZestHtml) {
+        let export = Export::new(
+            &results,
+            workspace,
+            &contract_styles,
+            branch,
+            analyses,
+        )?;
+
+        if output_types.contains(&OutputType::Json) {
+            export.write(OutputType::Json.path(coverage_dir))?;
+        }
+        if output_types.contains(&OutputType::ZestHtml) {
+            html::write(
+                &html::Report {
+                    export: &export,
+                    analyses,
+                    results: &results,
+                    source_root: &workspace.root,
+                    diff_coverage: diff_coverage.as_ref(),
+                    test_matrix: test_matrix.as_ref(),
+                    failed_tests: &failed_tests,
+                    grcov_html: output_types.contains(&OutputType::Html),
+                },
+                html_templates.as_deref(),
+                OutputType::ZestHtml.path(coverage_dir),
+            )?;
+        }
+    }
+
+    if output_types.contains(&OutputType::Sarif) {
+        sarif::write(
+            &sarif::sarif(
+                &workspace.root,
+                &instruction_coverage,
+                &dispatch_coverage,
+                &error_coverage,
+            ),
+            OutputType::Sarif.path(coverage_dir),
+        )?;
+    }
+
+    // NOTE: experimentation with `tarpaulin` as a backend
+    //       `branch_coverage` is stubbed, not useful to us
+    // {
+    //     // `cargo tarpaulin --skip-clean --out Html --engine Llvm --output-dir target/coverage`
+    //     let mut config: tarpaulin::config::Config = Default::default();
+    //     config.command = tarpaulin::config::Mode::Test;
+    //     config.set_clean(false);
+    //     config.generate = vec![tarpaulin::config::OutputFile::Html];
+    //     config.set_engine(tarpaulin::config::TraceEngine::Llvm);
+    //     config.branch_coverage = branch;
+    //     config.output_directory = Some("./target/coverage/".into());
+    //     config.test_timeout = std::time::Duration::from_secs(120);
+    //     config.exclude_path(&PathBuf::from("*tests*"));
+    //
+    //     tarpaulin::run(&[config]);
+    // }
+
+    // NOTE: run grcov (old way, through CLI)
+    //       depends on CLI, tradeoffs
+    // {
+    //     // grcov program/coverage --llvm -t html -o target/coverage
+    //     let mut cmd = Command::new("grcov")
+    //         .args([program_DIR, "--llvm", "-t", "html", "-o", program_DIR])
+    //         .spawn()?;
+    //
+    //     cmd.wait()?;
+    // }
+
+    // NOTE: Report regenerated outputs (and possibly open, if applicable)
+    output_types.iter().unique().try_for_each(|output_type| {
+        match output_type {
+            OutputType::Html | OutputType::ZestHtml => {
+                let index = output_type.path(coverage_dir).join("index.html");
+                eprintln!(
+                    "Successfully generated {} report at {}",
+                    output_type
+                        .to_possible_value()
+                        .map_or_else(String::new, |value| value.get_name().to_string()),
+                    index.display(),
+                );
+                // NOTE: grcov's report is linked from zest's own, only the latter is opened
+                if *output_type == OutputType::Html
+                    && output_types.contains(&OutputType::ZestHtml)
+                {
+                    return Ok(());
+                }
+                // open::that("./target/coverage/tarpaulin-report.html")
+                open::that(index)
+            }
+            output_type => {
+                eprintln!(
+                    "Successfully generated {} report, you can find it at {}",
+                    output_type
+                        .to_possible_value()
+                        .map_or_else(String::new, |value| value.get_name().to_string()),
+                    output_type.path(coverage_dir).display(),
+                );
+                Ok(())
+            }
+        }
+    })?;
+
+    if !failed_tests.is_empty() {
+        test_output::print_failed_tests(&failed_tests);
+    }
+
+    if let Some(diff_coverage) = &diff_coverage {
+        diff_coverage.print();
+    }
+
+    if !instruction_coverage.handlers.is_empty() {
+        instruction_coverage.print();
+    }
+
+    if !dispatch_coverage.variants.is_empty() {
+        dispatch_coverage.print();
+    }
+
+    if !error_coverage.errors.is_empty() {
+        error_coverage.print();
+    }
+
+    let mut summary = Summary::new(summary, &results, workspace, &function_span_coverage);
+    match summary_top {
+        Some(n) => summary.least_covered(n, summary_sort),
+        None => summary.sort(summary_sort),
+    }
+    summary.print(ColorThresholds {
+        low: summary_low,
+        high: summary_high,
+    });
+
+    // NOTE: compared before saving, so that the same file can be used for both
+    let current_baseline = Baseline::from_results(&results);
+    let regressions = compare_baseline
+        .map(|path| -> eyre::Result<_> {
+            let regressions = Baseline::read(&path)?.regressions(&current_baseline);
+            if regressions.is_empty() {
+                eprintln!("No coverage regressions compared to {}", path.display());
+            } else {
+                eprintln!("Coverage regressions compared to {}:", path.display());
+                eprintln!("{}", baseline::table(&regressions));
+            }
+
+            Ok(regressions)
+        })
+        .transpose()?
+        .unwrap_or_default();
+    if let Some(path) = save_baseline {
+        current_baseline.write(&path)?;
+        eprintln!("Saved coverage baseline to {}", path.display());
+    }
+
+    // NOTE: checked last, so that the reports are still generated
+    let mut violations = thresholds::check(
+        &results,
+        &Threshold {
+            lines: fail_under_lines,
+            functions: fail_under_functions,
+            branches: fail_under_branches,
+            ..Default::default()
+        },
+        &thresholds,
+        &workspace.members,
+    )?;
+    if let (Some(diff_coverage), Some(expected)) = (&diff_coverage, fail_under_diff) {
+        violations.extend(thresholds::Violation::check(
+            "(diff)",
+            "lines",
+            diff_coverage.lines,
+            expected,
+        ));
+    }
+    if !violations.is_empty() {
+        eprintln!("{}", thresholds::table(&violations));
+        bail!("{} coverage threshold(s) not met", violations.len());
+    }
+    if fail_on_regression && !regressions.is_empty() {
+        bail!("{} coverage regression(s) found", regressions.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_thresholds_before_running() {
+        let config = Config {
+            fail_under_branches: Some(80.0),
+            ..Default::default()
+        };
+        assert!(config.validate(true).is_ok());
+        assert!(config.validate(false).is_err());
+
+        let config = Config {
+            thresholds: vec![Threshold {
+                path: Some("src/*".to_string()),
+                lines: Some(120.0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(true),
+            Err("The `lines` threshold must be between 0 and 100, got 120".to_string())
+        );
+    }
+}
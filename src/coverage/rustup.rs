@@ -1,5 +1,6 @@
 use std::{
     env,
+    path::Path,
     process::{Command, Stdio},
 };
 
@@ -22,8 +23,11 @@ pub fn is_rustup_managed() -> bool {
     false
 }
 
+/// Add the `llvm-tools-preview` component to the toolchain of the project in `project_dir` (i.e.
+/// its `rust-toolchain.toml`, if any, unless `compiler_version` is given)
 pub fn install_llvm_tools(
-    compiler_version: Option<impl AsRef<str>>
+    compiler_version: Option<impl AsRef<str>>,
+    project_dir: impl AsRef<Path>,
 ) -> eyre::Result<()> {
     let mut spinner =
         Spinner::new(Spinners::Dots, "Installing toolchain (with `llvm-tools-preview` component)...".to_string());
@@ -32,6 +36,7 @@ pub fn install_llvm_tools(
         .arg("component")
         .arg("add")
         .arg("llvm-tools-preview")
+        .current_dir(project_dir)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()?;
@@ -0,0 +1,420 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use eyre::Context;
+use serde::Serialize;
+
+use super::{
+    dispatch::DispatchCoverage, errors::CustomErrorCoverage, instructions::InstructionCoverage,
+};
+
+pub const FILE_NAME: &str = "zest.sarif";
+pub const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+pub const VERSION: &str = "2.1.0";
+/// The base of the results' (relative) URIs, i.e. the project root
+const SRCROOT: &str = "%SRCROOT%";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Warning,
+    Note,
+}
+
+/// (id, name, description, level) of each of the rules
+const RULES: [(&str, &str, &str, Level); 4] = [
+    (
+        "zest/unexecuted-handler",
+        "UnexecutedHandler",
+        "A `#[program]` instruction handler which was never executed by the tests",
+        Level::Warning,
+    ),
+    (
+        "zest/undispatched-instruction",
+        "UndispatchedInstruction",
+        "An instruction dispatch arm which was never executed by the tests",
+        Level::Warning,
+    ),
+    (
+        "zest/wildcard-dispatched-instruction",
+        "WildcardDispatchedInstruction",
+        "An instruction enum variant without a dispatch arm of its own (only handled by a wildcard arm)",
+        Level::Note,
+    ),
+    (
+        "zest/untriggered-error",
+        "UntriggeredError",
+        "A custom program error which was never returned in the tests",
+        Level::Warning,
+    ),
+];
+
+/// A SARIF 2.1.0 log, only with the parts zest fills in
+#[derive(Debug, Clone, Serialize)]
+pub struct Sarif {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Run {
+    pub tool: Tool,
+    pub original_uri_base_ids: BTreeMap<&'static str, ArtifactLocation>,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tool {
+    pub driver: Driver,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub information_uri: &'static str,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub short_description: Message,
+    pub default_configuration: Configuration,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Configuration {
+    pub level: Level,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: &'static str,
+    pub rule_index: usize,
+    pub level: Level,
+    pub message: Message,
+    pub locations: Vec<Location>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    pub region: Region,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactLocation {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri_base_id: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: u32,
+    pub end_line: u32,
+}
+
+/// A SARIF 2.1.0 log with a result per untested program entry point or error path, located
+/// relative to the project root (`%SRCROOT%`)
+pub fn sarif(
+    root: &Path,
+    instruction_coverage: &InstructionCoverage,
+    dispatch_coverage: &DispatchCoverage,
+    error_coverage: &CustomErrorCoverage,
+) -> Sarif {
+    let result = |id: &str, text: String, rel_path: &Path, start_line: u32, end_line: u32| {
+        let rule_index = RULES.iter().position(|(rule, ..)| *rule == id).unwrap();
+        let (rule_id, _, _, level) = RULES[rule_index];
+        SarifResult {
+            rule_id,
+            rule_index,
+            level,
+            message: Message { text },
+            locations: vec![Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation {
+                        uri: rel_path.display().to_string(),
+                        uri_base_id: Some(SRCROOT),
+                    },
+                    region: Region {
+                        start_line,
+                        end_line,
+                    },
+                },
+            }],
+        }
+    };
+
+    let mut results = vec![];
+
+    for handler in &instruction_coverage.handlers {
+        if handler.line_hits == 0 && handler.invocations.total == 0 {
+            results.push(result(
+                "zest/unexecuted-handler",
+                format!(
+                    "Handler `{}` of program `{}` is never executed by the tests",
+                    handler.name, handler.program
+                ),
+                &handler.rel_path,
+                handler.line,
+                handler.end_line,
+            ));
+        }
+    }
+
+    for variant in &dispatch_coverage.variants {
+        let instruction = format!("{}::{}", variant.enum_name, variant.variant);
+        match variant.hits {
+            Some(0) => results.push(result(
+                "zest/undispatched-instruction",
+                format!(
+                    "Instruction `{}` of program `{}` is never dispatched by the tests",
+                    instruction, variant.program
+                ),
+                &variant.rel_path,
+                variant.line,
+                variant.end_line,
+            )),
+            None => results.push(result(
+                "zest/wildcard-dispatched-instruction",
+                format!(
+                    "Instruction `{}` of program `{}` has no dispatch arm of its own",
+                    instruction, variant.program
+                ),
+                &variant.rel_path,
+                variant.line,
+                variant.end_line,
+            )),
+            Some(_) => {}
+        }
+    }
+
+    for error in &error_coverage.errors {
+        if error.triggered == 0 {
+            results.push(result(
+                "zest/untriggered-error",
+                format!(
+                    "Error `{}` ({}) of program `{}` is never returned in the tests",
+                    error.name, error.code, error.program
+                ),
+                &error.rel_path,
+                error.line,
+                error.line,
+            ));
+        }
+    }
+
+    Sarif {
+        schema: SCHEMA,
+        version: VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "zest",
+                    version: env!("CARGO_PKG_VERSION"),
+                    information_uri: "https://github.com/LimeChain/zest",
+                    rules: RULES
+                        .iter()
+                        .map(|(id, name, description, level)| Rule {
+                            id,
+                            name,
+                            short_description: Message {
+                                text: description.to_string(),
+                            },
+                            default_configuration: Configuration { level: *level },
+                        })
+                        .collect(),
+                },
+            },
+            original_uri_base_ids: BTreeMap::from([(
+                SRCROOT,
+                ArtifactLocation {
+                    uri: format!("file://{}/", root.display()),
+                    uri_base_id: None,
+                },
+            )]),
+            results,
+        }],
+    }
+}
+
+pub fn write(
+    sarif: &Sarif,
+    path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let path = path.as_ref();
+    let json = serde_json::to_string_pretty(sarif)?;
+    fs::write(path, json).with_context(|| format!("Could not write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::coverage::{
+        dispatch::VariantCoverage,
+        errors::ErrorCoverage,
+        instructions::{HandlerCoverage, Invocations},
+        stats::Counter,
+    };
+
+    #[test]
+    fn reports_untested_entry_points_and_errors() {
+        let rel_path = PathBuf::from("programs/counter/src/lib.rs");
+        let handler = |name: &str, line, line_hits| HandlerCoverage {
+            program: "counter".to_string(),
+            name: name.to_string(),
+            rel_path: rel_path.clone(),
+            line,
+            end_line: line + 4,
+            line_hits,
+            lines: Counter::default(),
+            invocations: Invocations::default(),
+        };
+        let variant = |name: &str, line, hits| VariantCoverage {
+            program: "native".to_string(),
+            enum_name: "CounterInstruction".to_string(),
+            variant: name.to_string(),
+            rel_path: PathBuf::from("src/lib.rs"),
+            line,
+            end_line: line + 1,
+            hits,
+            calls: vec![],
+        };
+        let error = |name: &str, code, line, triggered| ErrorCoverage {
+            program: "counter".to_string(),
+            name: name.to_string(),
+            code,
+            rel_path: rel_path.clone(),
+            line,
+            triggered,
+        };
+
+        let sarif = sarif(
+            Path::new("/project"),
+            &InstructionCoverage {
+                handlers: vec![handler("initialize", 8, 1), handler("close", 20, 0)],
+            },
+            &DispatchCoverage {
+                variants: vec![
+                    variant("Initialize", 16, Some(2)),
+                    variant("Increment", 17, Some(0)),
+                    variant("Secret", 3, None),
+                ],
+                dispatched: Counter::new(1, 3),
+            },
+            &CustomErrorCoverage {
+                errors: vec![
+                    error("CounterError::Overflow", 6000, 33, 1),
+                    error("CounterError::Locked", 6001, 34, 0),
+                ],
+                triggered: Counter::new(1, 2),
+            },
+        );
+        let sarif = serde_json::to_value(sarif).unwrap();
+
+        assert_eq!(sarif["$schema"], SCHEMA);
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|rule| (
+                    rule["id"].as_str().unwrap(),
+                    rule["defaultConfiguration"]["level"].as_str().unwrap()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("zest/unexecuted-handler", "warning"),
+                ("zest/undispatched-instruction", "warning"),
+                ("zest/wildcard-dispatched-instruction", "note"),
+                ("zest/untriggered-error", "warning"),
+            ]
+        );
+        assert_eq!(
+            run["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|result| {
+                    let location = &result["locations"][0]["physicalLocation"];
+                    json!([
+                        result["ruleId"],
+                        result["ruleIndex"],
+                        result["level"],
+                        location["artifactLocation"]["uri"],
+                        location["artifactLocation"]["uriBaseId"],
+                        location["region"]["startLine"],
+                        location["region"]["endLine"],
+                    ])
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                json!([
+                    "zest/unexecuted-handler",
+                    0,
+                    "warning",
+                    "programs/counter/src/lib.rs",
+                    "%SRCROOT%",
+                    20,
+                    24
+                ]),
+                json!([
+                    "zest/undispatched-instruction",
+                    1,
+                    "warning",
+                    "src/lib.rs",
+                    "%SRCROOT%",
+                    17,
+                    18
+                ]),
+                json!([
+                    "zest/wildcard-dispatched-instruction",
+                    2,
+                    "note",
+                    "src/lib.rs",
+                    "%SRCROOT%",
+                    3,
+                    4
+                ]),
+                json!([
+                    "zest/untriggered-error",
+                    3,
+                    "warning",
+                    "programs/counter/src/lib.rs",
+                    "%SRCROOT%",
+                    34,
+                    34
+                ]),
+            ]
+        );
+        assert_eq!(run["originalUriBaseIds"]["%SRCROOT%"]["uri"], "file:///project/");
+    }
+}
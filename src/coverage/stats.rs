@@ -0,0 +1,80 @@
+use std::ops::AddAssign;
+
+use grcov::CovResult;
+use serde::{Deserialize, Serialize};
+
+/// Covered out of total (lines, functions or branches)
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Counter {
+    pub covered: usize,
+    pub total: usize,
+}
+
+impl Counter {
+    pub fn new(
+        covered: usize,
+        total: usize,
+    ) -> Self {
+        Self { covered, total }
+    }
+
+    /// `None` if there is nothing to cover
+    pub fn percent(&self) -> Option<f64> {
+        (self.total != 0)
+            .then(|| self.covered as f64 / self.total as f64 * 100.0)
+    }
+}
+
+impl AddAssign for Counter {
+    fn add_assign(&mut self, rhs: Self) {
+        self.covered += rhs.covered;
+        self.total += rhs.total;
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct Stats {
+    pub lines: Counter,
+    pub functions: Counter,
+    pub branches: Counter,
+}
+
+impl Stats {
+    pub fn from_result(result: &CovResult) -> Self {
+        Self {
+            lines: Counter::new(
+                result.lines.values().filter(|hits| **hits > 0).count(),
+                result.lines.len(),
+            ),
+            functions: Counter::new(
+                result.functions.values().filter(|f| f.executed).count(),
+                result.functions.len(),
+            ),
+            branches: Counter::new(
+                result.branches.values().flatten().filter(|taken| **taken).count(),
+                result.branches.values().map(Vec::len).sum(),
+            ),
+        }
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.lines += rhs.lines;
+        self.functions += rhs.functions;
+        self.branches += rhs.branches;
+    }
+}
+
+impl<'a> FromIterator<&'a CovResult> for Stats {
+    fn from_iter<T: IntoIterator<Item = &'a CovResult>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::default(), |mut stats, result| {
+            stats += Self::from_result(result);
+            stats
+        })
+    }
+}
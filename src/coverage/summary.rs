@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use clap_serde_derive::clap::{self, ValueEnum};
+use grcov::ResultTuple;
+use serde::{Deserialize, Serialize};
+use tabled::{
+    builder::Builder,
+    settings::{object::Cell, Color},
+    Table,
+};
+
+use crate::util;
+
+use super::{
+    functions::FunctionSpanCoverage,
+    instructions::program_name,
+    stats::{Counter, Stats},
+    workspace::Workspace,
+};
+
+/// What each row of the summary table is
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SummaryLevel {
+    /// No summary
+    None,
+    /// Workspace member (or file outside of all members)
+    #[default]
+    Program,
+    File,
+    /// Contract function (lines and branches over its whole span)
+    Function,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SummarySort {
+    #[default]
+    Name,
+    /// Least covered first (the ones with nothing to cover last)
+    Lines,
+    Functions,
+    Branches,
+}
+
+impl SummarySort {
+    fn counter(
+        self,
+        stats: &Stats,
+    ) -> Option<Counter> {
+        match self {
+            SummarySort::Name => None,
+            SummarySort::Lines => Some(stats.lines),
+            SummarySort::Functions => Some(stats.functions),
+            SummarySort::Branches => Some(stats.branches),
+        }
+    }
+}
+
+/// Percentages below `low` are red, below `high` yellow and green otherwise
+#[derive(Debug, Clone, Copy)]
+pub struct ColorThresholds {
+    pub low: f64,
+    pub high: f64,
+}
+
+/// Line, function and branch coverage per program, file or function
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub level: SummaryLevel,
+    pub rows: Vec<(String, Stats)>,
+    /// Of all of the rows, even if only the least covered ones are kept
+    pub total: Stats,
+}
+
+impl Summary {
+    pub fn new(
+        level: SummaryLevel,
+        results: &[ResultTuple],
+        workspace: &Workspace,
+        function_span_coverage: &FunctionSpanCoverage,
+    ) -> Self {
+        let rows = match level {
+            SummaryLevel::None => vec![],
+            SummaryLevel::Program => {
+                let mut programs = BTreeMap::<String, Stats>::new();
+                for (path, rel_path, result) in results {
+                    *programs
+                        .entry(program_name(workspace, path, rel_path))
+                        .or_default() += Stats::from_result(result);
+                }
+                programs.into_iter().collect()
+            }
+            SummaryLevel::File => results
+                .iter()
+                .map(|(_, rel_path, result)| {
+                    (rel_path.display().to_string(), Stats::from_result(result))
+                })
+                .collect(),
+            SummaryLevel::Function => function_span_coverage
+                .functions
+                .iter()
+                .map(|function| {
+                    (
+                        format!("{}: {}", function.rel_path.display(), function.name),
+                        Stats {
+                            lines: function.lines,
+                            functions: Counter::new(function.executed as usize, 1),
+                            branches: function.branches,
+                        },
+                    )
+                })
+                .collect(),
+        };
+
+        let total = rows.iter().fold(Stats::default(), |mut total, (_, stats)| {
+            total += *stats;
+            total
+        });
+
+        let mut summary = Self { level, rows, total };
+        summary.sort(SummarySort::Name);
+        summary
+    }
+
+    pub fn sort(
+        &mut self,
+        sort: SummarySort,
+    ) {
+        self.rows.sort_by(|(a_name, a), (b_name, b)| {
+            // NOTE: `None` (nothing to cover) is sorted last
+            let percent = |stats| {
+                sort.counter(stats)
+                    .and_then(|counter| counter.percent())
+                    .unwrap_or(f64::INFINITY)
+            };
+            percent(a)
+                .total_cmp(&percent(b))
+                .then_with(|| a_name.cmp(b_name))
+        });
+    }
+
+    /// Only keep the `n` least covered rows, according to `sort` (lines if sorting by name)
+    pub fn least_covered(
+        &mut self,
+        n: usize,
+        sort: SummarySort,
+    ) {
+        let metric = match sort {
+            SummarySort::Name => SummarySort::Lines,
+            sort => sort,
+        };
+        self.sort(metric);
+        self.rows.truncate(n);
+        self.sort(sort);
+    }
+
+    pub fn table(
+        &self,
+        colors: Option<ColorThresholds>,
+    ) -> Table {
+        let name = match self.level {
+            SummaryLevel::None | SummaryLevel::Program => "Program",
+            SummaryLevel::File => "File",
+            SummaryLevel::Function => "Function",
+        };
+        let total = self.total;
+
+        let mut builder = Builder::default();
+        builder.push_record([name, "Lines", "Functions", "Branches"]);
+        for (name, stats) in self.rows.iter().chain([&("Total".to_string(), total)]) {
+            builder.push_record([
+                name.clone(),
+                format_counter(stats.lines),
+                format_counter(stats.functions),
+                format_counter(stats.branches),
+            ]);
+        }
+        let mut table = builder.build();
+
+        if let Some(ColorThresholds { low, high }) = colors {
+            for (row, (_, stats)) in self
+                .rows
+                .iter()
+                .chain([&(String::new(), total)])
+                .enumerate()
+            {
+                for (column, counter) in [stats.lines, stats.functions, stats.branches]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let Some(percent) = counter.percent() else {
+                        continue;
+                    };
+                    let color = if percent < low {
+                        Color::FG_RED
+                    } else if percent < high {
+                        Color::FG_YELLOW
+                    } else {
+                        Color::FG_GREEN
+                    };
+                    // NOTE: the header is the first row and the name the first column
+                    table.modify(Cell::new(row + 1, column + 1), color);
+                }
+            }
+        }
+
+        table
+    }
+
+    /// Colored only if stderr is a terminal (and `NO_COLOR` is not set)
+    pub fn print(
+        &self,
+        colors: ColorThresholds,
+    ) {
+        if self.rows.is_empty() {
+            return;
+        }
+
+        let colors = util::use_colors(&std::io::stderr()).then_some(colors);
+        eprintln!("Coverage summary:");
+        eprintln!("{}", self.table(colors));
+    }
+}
+
+fn format_counter(counter: Counter) -> String {
+    match counter.percent() {
+        Some(percent) => format!("{:.2}% ({}/{})", percent, counter.covered, counter.total),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_least_covered() {
+        let stats = |covered, total| Stats {
+            lines: Counter::new(covered, total),
+            ..Default::default()
+        };
+        let mut summary = Summary {
+            level: SummaryLevel::File,
+            rows: vec![
+                ("a.rs".to_string(), stats(9, 10)),
+                ("b.rs".to_string(), stats(0, 0)),
+                ("c.rs".to_string(), stats(1, 10)),
+                ("d.rs".to_string(), stats(5, 10)),
+            ],
+            total: stats(15, 30),
+        };
+
+        summary.least_covered(2, SummarySort::Name);
+
+        assert_eq!(
+            summary.rows.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(),
+            vec!["c.rs", "d.rs"]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use eyre::Context;
+use itertools::Itertools;
+
+pub const FAILED_TESTS_FILE_NAME: &str = "failed_tests.txt";
+pub const FAILED_TESTS_HTML_FILE_NAME: &str = "failed_tests.html";
+/// Combined output of all test runs, kept for `zest report`
+pub const TEST_LOG_FILE_NAME: &str = "test_output.log";
+
+/// Names of the failed tests in the (`libtest`) output of `cargo test`
+pub fn parse_failed_tests(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("test ")?
+                .strip_suffix(" ... FAILED")
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Names of the failed tests in the (status lines of the) output of `cargo nextest run`, e.g.
+/// `FAIL [   0.004s] counter tests::test_overflow` or `TRY 3 SIGSEGV [   0.010s] ...`
+///
+/// Only the last attempt of a retried test counts, i.e. flaky tests are not failures
+pub fn parse_failed_nextest_tests(stderr: &str) -> Vec<String> {
+    const FAILED_STATUSES: [&str; 6] =
+        ["FAIL", "SIGSEGV", "SIGABRT", "SIGBUS", "ABORT", "TIMEOUT"];
+
+    let statuses = stderr.lines().filter_map(|line| {
+        let mut words = line.split_whitespace().peekable();
+        // NOTE: retried tests are prefixed with `TRY <n>`
+        if words.next_if_eq(&"TRY").is_some() {
+            words.next()?;
+        }
+        let status = words.next()?;
+        words.next()?.starts_with('[').then_some(())?;
+
+        // NOTE: `[   0.004s] <binary id> <test name>`, the test name is last
+        Some((words.last()?, FAILED_STATUSES.contains(&status)))
+    });
+
+    let mut failed: Vec<&str> = vec![];
+    for (test, is_failure) in statuses {
+        failed.retain(|failed_test| *failed_test != test);
+        if is_failure {
+            failed.push(test);
+        }
+    }
+
+    failed.into_iter().map(str::to_string).collect()
+}
+
+/// Append the output of a test run to the test log
+pub fn append_test_log(
+    path: impl AsRef<Path>,
+    stdout: &[u8],
+    stderr: &[u8],
+) -> eyre::Result<()> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Could not open {}", path.display()))?;
+    file.write_all(stdout)?;
+    file.write_all(stderr)?;
+
+    Ok(())
+}
+
+pub fn print_failed_tests(failed_tests: &[String]) {
+    eprintln!(
+        "{} test(s) failed, coverage only includes what they executed before failing:",
+        failed_tests.len()
+    );
+    failed_tests.iter().for_each(|test| eprintln!("  - {}", test));
+}
+
+pub fn write_failed_tests(
+    failed_tests: &[String],
+    path: impl AsRef<Path>,
+) -> eyre::Result<()> {
+    let path = path.as_ref();
+    fs::write(path, failed_tests.iter().join("\n"))
+        .with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// For the failed tests page of the HTML report
+pub fn failed_tests_html_context(failed_tests: &[String]) -> tera::Context {
+    let mut context = tera::Context::new();
+    context.insert("failed_tests", failed_tests);
+    context
+}
+
+#[rustfmt::skip]
+pub const FAILED_TESTS_HTML_TEMPLATE: &str = /* html */ r#"
+{% extends "base.html" %}
+{% block title %}failed tests{% endblock title %}
+{% block content %}
+  <h1>Failed tests</h1>
+  <p>{{ failed_tests | length }} test(s) failed, the coverage report only includes what they executed before failing</p>
+  <ul>
+    {% for test in failed_tests %}<li><code>{{ test }}</code></li>{% endfor %}
+  </ul>
+{% endblock content %}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_failed_tests() {
+        let stdout = "\
+running 3 tests
+test test_increment ... ok
+test tests::test_overflow ... FAILED
+test test_initialize ... ignored
+
+failures:
+
+---- tests::test_overflow stdout ----
+thread 'tests::test_overflow' panicked at tests/integration.rs:42:5
+
+failures:
+    tests::test_overflow
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out
+";
+
+        assert_eq!(parse_failed_tests(stdout), vec!["tests::test_overflow"]);
+    }
+
+    #[test]
+    fn parses_failed_nextest_tests() {
+        let stderr = "\
+    Starting 3 tests across 1 binary
+        PASS [   0.003s] counter tests::test_increment
+        FAIL [   0.004s] counter tests::test_overflow
+  TRY 2 FAIL [   0.004s] counter tests::test_flaky
+  TRY 3 PASS [   0.004s] counter tests::test_flaky
+------------
+     Summary [   0.010s] 3 tests run: 2 passed (1 flaky), 1 failed, 0 skipped
+        FAIL [   0.004s] counter tests::test_overflow
+error: test run failed
+";
+
+        assert_eq!(
+            parse_failed_nextest_tests(stderr),
+            vec!["tests::test_overflow"]
+        );
+    }
+}
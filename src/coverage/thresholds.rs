@@ -0,0 +1,175 @@
+use eyre::{bail, Context};
+use globset::Glob;
+use grcov::ResultTuple;
+use serde::{Deserialize, Serialize};
+use tabled::{Table, Tabled};
+
+use super::{
+    stats::{Counter, Stats},
+    workspace::Package,
+};
+
+/// Minimum coverage percentages, for either a set of files (`path` glob, relative to the project
+/// root, checked per file) or a whole workspace member (`package`, checked in total)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub path: Option<String>,
+    pub package: Option<String>,
+    pub lines: Option<f64>,
+    pub functions: Option<f64>,
+    pub branches: Option<f64>,
+}
+
+#[derive(Debug, Clone, Tabled)]
+pub struct Violation {
+    #[tabled(rename = "Subject")]
+    pub subject: String,
+    #[tabled(rename = "Metric")]
+    pub metric: &'static str,
+    #[tabled(rename = "Coverage", display_with = "display_percent")]
+    pub actual: f64,
+    #[tabled(rename = "Threshold", display_with = "display_percent")]
+    pub expected: f64,
+}
+
+fn display_percent(percent: &f64) -> String {
+    format!("{:.2}%", percent)
+}
+
+impl Violation {
+    /// `None` if met (or there is nothing to cover)
+    pub fn check(
+        subject: &str,
+        metric: &'static str,
+        counter: Counter,
+        expected: f64,
+    ) -> Option<Self> {
+        let actual = counter.percent()?;
+
+        (actual < expected).then(|| Self {
+            subject: subject.to_string(),
+            metric,
+            actual,
+            expected,
+        })
+    }
+}
+
+impl Threshold {
+    fn violations(
+        &self,
+        subject: &str,
+        stats: &Stats,
+    ) -> Vec<Violation> {
+        [
+            ("lines", self.lines, stats.lines),
+            ("functions", self.functions, stats.functions),
+            ("branches", self.branches, stats.branches),
+        ]
+        .into_iter()
+        .filter_map(|(metric, expected, counter)| {
+            Violation::check(subject, metric, counter, expected?)
+        })
+        .collect()
+    }
+}
+
+/// Check the `total` threshold against all results and each of the `thresholds` against the
+/// results they select
+pub fn check(
+    results: &[ResultTuple],
+    total: &Threshold,
+    thresholds: &[Threshold],
+    packages: &[Package],
+) -> eyre::Result<Vec<Violation>> {
+    let mut violations = total.violations(
+        "(total)",
+        &results.iter().map(|(_, _, result)| result).collect(),
+    );
+
+    for threshold in thresholds {
+        match (&threshold.path, &threshold.package) {
+            (Some(path), None) => {
+                let glob = Glob::new(path)
+                    .with_context(|| format!("Invalid threshold path `{}`", path))?
+                    .compile_matcher();
+
+                results
+                    .iter()
+                    .filter(|(_, rel_path, _)| glob.is_match(rel_path))
+                    .for_each(|(_, rel_path, result)| {
+                        violations.extend(threshold.violations(
+                            &rel_path.display().to_string(),
+                            &Stats::from_result(result),
+                        ))
+                    });
+            }
+            (None, Some(package_name)) => {
+                let Some(package) =
+                    packages.iter().find(|p| &p.name == package_name)
+                else {
+                    bail!("Unknown threshold package `{}`", package_name);
+                };
+
+                let stats = results
+                    .iter()
+                    .filter(|(path, _, _)| package.contains(path))
+                    .map(|(_, _, result)| result)
+                    .collect();
+                violations.extend(
+                    threshold.violations(&format!("package `{}`", package_name), &stats),
+                );
+            }
+            _ => bail!(
+                "Each threshold must specify exactly one of `path` or `package`: {:?}",
+                threshold
+            ),
+        }
+    }
+
+    Ok(violations)
+}
+
+pub fn table(violations: &[Violation]) -> Table {
+    Table::new(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use grcov::CovResult;
+
+    #[test]
+    fn reports_only_missed_thresholds() {
+        let results = vec![(
+            "/project/src/lib.rs".into(),
+            "src/lib.rs".into(),
+            CovResult {
+                lines: [(1, 1), (2, 0), (3, 4), (4, 0)].into_iter().collect(),
+                branches: Default::default(),
+                functions: Default::default(),
+            },
+        )];
+
+        let total = Threshold {
+            lines: Some(40.0),
+            ..Default::default()
+        };
+        let per_file = Threshold {
+            path: Some("src/*".to_string()),
+            lines: Some(75.0),
+            // NOTE: there are no branches, so it can't be violated
+            branches: Some(100.0),
+            ..Default::default()
+        };
+
+        let violations = check(&results, &total, &[per_file], &[]).unwrap();
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].subject, "src/lib.rs");
+        assert_eq!(violations[0].metric, "lines");
+        assert_eq!(violations[0].actual, 50.0);
+    }
+}
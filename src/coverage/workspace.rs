@@ -0,0 +1,170 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use eyre::{bail, Context};
+use itertools::Itertools;
+use serde::Deserialize;
+
+/// The parts of `cargo metadata`'s output that are relevant to us
+#[derive(Debug, Clone, Deserialize)]
+pub struct Workspace {
+    #[serde(rename = "workspace_root")]
+    pub root: PathBuf,
+    #[serde(rename = "target_directory")]
+    pub target_dir: PathBuf,
+    #[serde(rename = "packages")]
+    pub members: Vec<Package>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dependency {
+    /// Name of the depended on package (not its `rename`)
+    pub name: String,
+    /// `None` for normal dependencies, `dev`/`build` otherwise
+    pub kind: Option<String>,
+}
+
+impl Package {
+    /// Directory containing the package's `Cargo.toml`
+    pub fn dir(&self) -> &Path {
+        self.manifest_path
+            .parent()
+            .expect("`manifest_path` always points to a file")
+    }
+
+    /// Whether the package (normally, not only for tests or builds) depends on `name`
+    pub fn depends_on(
+        &self,
+        name: &str,
+    ) -> bool {
+        self.dependencies
+            .iter()
+            .any(|dependency| dependency.kind.is_none() && dependency.name == name)
+    }
+
+    /// Whether the (absolute) `path` is inside of the package's directory
+    pub fn contains(
+        &self,
+        path: &Path,
+    ) -> bool {
+        path.starts_with(self.dir())
+    }
+}
+
+impl Workspace {
+    pub fn from_manifest_path(
+        manifest_path: impl AsRef<Path>,
+        compiler_version: Option<impl AsRef<str>>,
+    ) -> eyre::Result<Self> {
+        let manifest_path = manifest_path.as_ref();
+
+        let cmd = Command::new("cargo")
+            .args(compiler_version.map(|cv| format!("+{}", cv.as_ref())))
+            .arg("metadata")
+            // NOTE: only workspace members are listed in `packages` with `--no-deps`
+            .args(["--format-version", "1", "--no-deps"])
+            .arg("--manifest-path")
+            .arg(manifest_path)
+            .current_dir(project_dir(manifest_path))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let output = cmd.wait_with_output()?;
+        if !output.status.success() {
+            eprintln!("cargo metadata stderr:");
+            eprintln!("{}", std::str::from_utf8(&output.stderr)?);
+            bail!("`cargo metadata` failed for {}", manifest_path.display());
+        }
+
+        serde_json::from_slice(&output.stdout).with_context(|| {
+            format!(
+                "Could not parse `cargo metadata` output for {}",
+                manifest_path.display()
+            )
+        })
+    }
+
+    /// Resolve the workspace members selected with `--package`/`--exclude`
+    ///
+    /// Returns `None` if no selection was made (i.e. `cargo`'s default members are used)
+    pub fn select(
+        &self,
+        packages: &[String],
+        exclude: &[String],
+    ) -> eyre::Result<Option<Vec<&Package>>> {
+        if !packages.is_empty() && !exclude.is_empty() {
+            bail!("`--package` and `--exclude` cannot be used together");
+        }
+
+        let unknown = packages
+            .iter()
+            .chain(exclude)
+            .filter(|name| !self.members.iter().any(|p| &p.name == *name))
+            .collect_vec();
+        if !unknown.is_empty() {
+            bail!(
+                "Unknown workspace member(s): {} (available: {})",
+                unknown.iter().join(", "),
+                self.members.iter().map(|p| &p.name).join(", "),
+            );
+        }
+
+        let selected = if !packages.is_empty() {
+            self.members
+                .iter()
+                .filter(|p| packages.contains(&p.name))
+                .collect()
+        } else if !exclude.is_empty() {
+            self.members
+                .iter()
+                .filter(|p| !exclude.contains(&p.name))
+                .collect()
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(selected))
+    }
+}
+
+/// Directory of the (canonical) `manifest_path`, which `cargo` and `rustup` are run in, so that
+/// the project's `rust-toolchain.toml` is used even if zest is started elsewhere
+pub fn project_dir(manifest_path: &Path) -> &Path {
+    manifest_path
+        .parent()
+        .expect("`manifest_path` always points to a file")
+}
+
+/// Arguments to pass to `cargo {build,test}` for the given selection
+pub fn package_args(
+    packages: &[String],
+    exclude: &[String],
+) -> Vec<String> {
+    if !packages.is_empty() {
+        packages
+            .iter()
+            .flat_map(|p| ["--package".to_string(), p.clone()])
+            .collect()
+    } else if !exclude.is_empty() {
+        // NOTE: `cargo` only accepts `--exclude` together with `--workspace`
+        std::iter::once("--workspace".to_string())
+            .chain(
+                exclude
+                    .iter()
+                    .flat_map(|p| ["--exclude".to_string(), p.clone()]),
+            )
+            .collect()
+    } else {
+        vec![]
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use clap_serde_derive::clap::{self, Parser};
+use eyre::{bail, ContextCompat};
+
+use crate::coverage::{per_test::TestMatrix, workspace::Workspace};
+
+#[derive(Parser, Debug, Clone)]
+pub struct Config {
+    #[arg(
+        value_name = "FILE:LINE|FILE:FUNCTION",
+        help = "Source location to explain, e.g. `programs/counter/src/lib.rs:42` or `programs/counter/src/lib.rs:increment`"
+    )]
+    pub location: String,
+
+    #[arg(long, default_value = ".", help = "Path to the solana project")]
+    pub path: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Path to the `Cargo.toml` of the project (defaults to `Cargo.toml` in `--path`)"
+    )]
+    pub manifest_path: Option<PathBuf>,
+}
+
+enum Target<'a> {
+    Line(u32),
+    Function(&'a str),
+}
+
+pub fn run(config: Config) -> eyre::Result<()> {
+    let Config {
+        location,
+        path,
+        manifest_path,
+    } = config;
+
+    let (file, target) = location
+        .rsplit_once(':')
+        .wrap_err("Expected a location in the form of `FILE:LINE` or `FILE:FUNCTION`")?;
+    let target = match target.parse::<u32>() {
+        Ok(line) => Target::Line(line),
+        Err(_) => Target::Function(target),
+    };
+
+    let manifest_path = manifest_path.unwrap_or_else(|| path.join("Cargo.toml"));
+    let workspace = Workspace::from_manifest_path(manifest_path, None::<&str>)?;
+
+    let matrix = TestMatrix::read(
+        workspace
+            .target_dir
+            .join("coverage")
+            .join(TestMatrix::FILE_NAME),
+    )?;
+
+    let file = relative_to_root(Path::new(file), &workspace.root);
+    if !matrix.files.contains_key(&file) {
+        bail!(
+            "{} is not part of the per-test coverage data",
+            file.display()
+        );
+    }
+
+    let (subject, tests) = match target {
+        Target::Line(line) => (
+            format!("{}:{}", file.display(), line),
+            matrix.tests_covering_line(&file, line),
+        ),
+        Target::Function(function) => (
+            format!("`{}` in {}", function, file.display()),
+            matrix.tests_covering_function(&file, function),
+        ),
+    };
+
+    if tests.is_empty() {
+        println!("{} is not covered by any test", subject);
+    } else {
+        println!("{} is covered by {} test(s):", subject, tests.len());
+        tests.iter().for_each(|test| println!("  - {}", test));
+    }
+
+    Ok(())
+}
+
+/// Paths in the `TestMatrix` (and in the coverage results) are relative to the workspace root
+pub(crate) fn relative_to_root(
+    file: &Path,
+    root: &Path,
+) -> PathBuf {
+    let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+
+    file.strip_prefix(root)
+        .or_else(|_| file.strip_prefix("."))
+        .unwrap_or(&file)
+        .to_path_buf()
+}
@@ -12,9 +12,9 @@ use serde_json::Value;
 use simplelog::{
     ColorChoice, Config, LevelFilter, TermLogger, TerminalMode, WriteLogger,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
-use std::ops::Deref;
+use std::ops::{Deref, RangeInclusive};
 use std::panic;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -23,7 +23,9 @@ use std::{process, thread};
 
 use grcov::*;
 
-use crate::coverage::ContractStyle;
+use crate::coverage::{
+    contract_style::ContractStyles, exclusions::Exclusions, FunctionCoverage,
+};
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum OutputType {
@@ -46,7 +48,8 @@ impl FromStr for OutputType {
             "ade" => Self::Ade,
             "lcov" => Self::Lcov,
             "coveralls" => Self::Coveralls,
-            "coveralls+" => Self::CoverallsPlus,
+            // NOTE: zest spells it `coveralls-plus` (kebab-case, like its other output types)
+            "coveralls+" | "coveralls-plus" => Self::CoverallsPlus,
             "files" => Self::Files,
             "covdir" => Self::Covdir,
             "html" => Self::Html,
@@ -58,7 +61,7 @@ impl FromStr for OutputType {
 }
 
 impl OutputType {
-    fn to_file_name(
+    pub fn to_file_name(
         &self,
         output_path: Option<&Path>,
     ) -> Option<PathBuf> {
@@ -127,7 +130,7 @@ impl ValueEnum for LevelFilterArg {
     }
 }
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(
     author,
     version,
@@ -170,7 +173,7 @@ pub struct Opt {
             - *coveralls* for the Coveralls specific format;\n\
             - *lcov* for the lcov INFO format;\n\
             - *covdir* for the covdir recursive JSON format;\n\
-            - *coveralls+* for the Coveralls specific format with function information;\n\
+            - *coveralls+* (or *coveralls-plus*) for the Coveralls specific format with function information;\n\
             - *ade* for the ActiveData-ETL specific format;\n\
             - *files* to only return a list of files.\n\
             - *markdown* for human easy read.\n\
@@ -180,6 +183,7 @@ pub struct Opt {
         requires_ifs = [
             ("coveralls", "coveralls-auth"),
             ("coveralls+", "coveralls-auth"),
+            ("coveralls-plus", "coveralls-auth"),
         ],
 
         value_delimiter = ',',
@@ -301,19 +305,61 @@ pub struct Opt {
     /// No symbol demangling.
     #[arg(long)]
     pub no_demangle: bool,
-    /// Style of contact
-    #[arg(long)]
-    pub contract_style: ContractStyle,
+    /// Style of contract of each file
+    #[arg(skip)]
+    pub contract_styles: ContractStyles,
+    /// Where the (contract) functions' coverage comes from
+    #[arg(skip)]
+    pub function_coverage: FunctionCoverage,
+    /// Tree-sitter queries, the nodes captured by which are excluded (along with the test-only
+    /// items, which always are)
+    #[arg(skip)]
+    pub excl_queries: Vec<String>,
+}
+
+/// How many times each function was executed, keyed by its (absolute) path and name
+///
+/// Only known with `FunctionCoverage::Records`, summed over the function records (e.g. generic
+/// instantiations) merged into each function
+pub type ExecutionCounts = HashMap<(PathBuf, String), u64>;
+
+/// Aggregate the coverage results and write them out as all of the requested `output_types`
+///
+/// The aggregated results are returned for further (`zest`-specific) processing
+pub fn main(opt: Opt) -> eyre::Result<(Vec<ResultTuple>, ExecutionCounts)> {
+    let (iterator, execution_counts) = aggregate(&opt)?;
+    if !opt.output_types.is_empty() {
+        output(opt, &iterator)?;
+    }
+
+    Ok((iterator, execution_counts))
+}
+
+fn num_threads(opt: &Opt) -> usize {
+    opt.threads.unwrap_or_else(|| 1.max(num_cpus::get() - 1))
+}
+
+fn source_root(opt: &Opt) -> Option<PathBuf> {
+    opt.source_dir
+        .clone()
+        .filter(|source_dir| source_dir != Path::new(""))
+        .map(|source_dir| {
+            canonicalize_path(source_dir)
+                .expect("Source directory does not exist.")
+        })
 }
 
-pub fn main(opt: Opt) -> eyre::Result<()> {
+/// Parse, collect and aggregate the coverage results (the part of `grcov`'s `main` before any
+/// outputs are generated)
+pub fn aggregate(opt: &Opt) -> eyre::Result<(Vec<ResultTuple>, ExecutionCounts)> {
     // dbg!(&opt.path_mapping, &opt.paths, &opt.llvm, &opt.prefix_dir);
 
-    if let Some(path) = opt.llvm_path {
-        LLVM_PATH.set(path).unwrap();
+    if let Some(path) = opt.llvm_path.clone() {
+        // NOTE: may already be set by a previous aggregation
+        let _ = LLVM_PATH.set(path);
     }
 
-    let filter_option = opt.filter.map(|filter| match filter {
+    let filter_option = opt.filter.as_ref().map(|filter| match filter {
         Filter::Covered => true,
         Filter::Uncovered => false,
     });
@@ -351,14 +397,13 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
     }
 
     let file_filter = FileFilter::new(
-        opt.excl_line,
-        opt.excl_start,
-        opt.excl_stop,
-        opt.excl_br_line,
-        opt.excl_br_start,
-        opt.excl_br_stop,
+        opt.excl_line.clone(),
+        opt.excl_start.clone(),
+        opt.excl_stop.clone(),
+        opt.excl_br_line.clone(),
+        opt.excl_br_start.clone(),
+        opt.excl_br_stop.clone(),
     );
-    let demangle = !opt.no_demangle;
 
     panic::set_hook(Box::new(|panic_info| {
         let (filename, line) = panic_info
@@ -379,17 +424,10 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
         error!("A panic occurred at {}:{}: {}", filename, line, cause);
     }));
 
-    let num_threads: usize =
-        opt.threads.unwrap_or_else(|| 1.max(num_cpus::get() - 1));
-    let source_root = opt
-        .source_dir
-        .filter(|source_dir| source_dir != Path::new(""))
-        .map(|source_dir| {
-            canonicalize_path(source_dir)
-                .expect("Source directory does not exist.")
-        });
+    let num_threads = num_threads(opt);
+    let source_root = source_root(opt);
 
-    let prefix_dir = opt.prefix_dir.or_else(|| source_root.clone());
+    let prefix_dir = opt.prefix_dir.clone().or_else(|| source_root.clone());
 
     let tmp_dir =
         tempfile::tempdir().expect("Failed to create temporary directory");
@@ -400,14 +438,16 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
         FxHashMap::with_capacity_and_hasher(20_000, Default::default()),
     ));
     let (sender, receiver) = bounded(2 * num_threads);
+    // NOTE: the producer's work items go through the relay, see below
+    let (relay_sender, relay_receiver): (JobSender, JobReceiver) = bounded(2 * num_threads);
     let path_mapping: Arc<Mutex<Option<Value>>> = Arc::new(Mutex::new(None));
 
     let producer = {
-        let sender: JobSender = sender.clone();
+        let sender: JobSender = relay_sender.clone();
         let tmp_path = tmp_path.clone();
-        let path_mapping_file = opt.path_mapping;
+        let path_mapping_file = opt.path_mapping.clone();
         let path_mapping = Arc::clone(&path_mapping);
-        let paths = opt.paths;
+        let paths = opt.paths.clone();
         let is_llvm = opt.llvm;
 
         thread::Builder::new()
@@ -435,6 +475,61 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
             .unwrap()
     };
 
+    // NOTE: grcov's lcov parser only keeps whether each function record was executed, so the
+    //       profiles are converted to lcov here (instead of in the consumers) to also count
+    //       the executions of the records
+    let relay = {
+        let sender: JobSender = sender.clone();
+        let binary_path = opt.binary_path.clone();
+        let working_dir = tmp_path.join("relay");
+
+        thread::Builder::new()
+            .name(String::from("Relay"))
+            .spawn(move || {
+                fs::create_dir(&working_dir)
+                    .expect("Failed to create working directory");
+                let mut record_counts = HashMap::new();
+
+                while let Ok(Some(work_item)) = relay_receiver.recv() {
+                    let work_item = match work_item {
+                        WorkItem {
+                            format: ItemFormat::Profraw,
+                            item: ItemType::Paths(profraw_paths),
+                            name,
+                        } if binary_path.is_some() => {
+                            let lcovs = match profraws_to_lcov(
+                                &profraw_paths,
+                                binary_path.as_ref().unwrap(),
+                                &working_dir,
+                            ) {
+                                Ok(lcovs) => lcovs,
+                                Err(e) => {
+                                    error!("Error while executing llvm tools: {}", e);
+                                    continue;
+                                }
+                            };
+                            for lcov in lcovs {
+                                count_records(&lcov, &mut record_counts);
+                                sender
+                                    .send(Some(WorkItem {
+                                        format: ItemFormat::Info,
+                                        item: ItemType::Content(lcov),
+                                        name: name.clone(),
+                                    }))
+                                    .unwrap();
+                            }
+                            continue;
+                        }
+                        work_item => work_item,
+                    };
+                    sender.send(Some(work_item)).unwrap();
+                }
+
+                record_counts
+            })
+            .unwrap()
+    };
+
     let mut parsers = Vec::new();
 
     for i in 0..num_threads {
@@ -470,6 +565,11 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
         process::exit(1);
     }
 
+    relay_sender.send(None).unwrap();
+    let Ok(record_counts) = relay.join() else {
+        process::exit(1);
+    };
+
     // Poison the receiver, now that the producer is finished.
     for _ in 0..num_threads {
         sender.send(None).unwrap();
@@ -498,6 +598,7 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
         filter_option,
         file_filter,
     );
+    let mut execution_counts = ExecutionCounts::new();
     let iterator = {
         let mut iterator = iterator;
 
@@ -506,7 +607,11 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
             iterator.retain(|(path, _, _)| path.starts_with(source_root));
         }
 
-        // NOTE: line-based `functions` coverage
+        let excl_queries = Exclusions::compile_queries(&opt.excl_queries)?;
+        let execution_counts = &mut execution_counts;
+
+        // NOTE: `functions` coverage, only of the contract functions (and without the
+        //       excluded nodes)
         iterator = iterator
             .into_iter()
             .map(
@@ -516,40 +621,82 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
                     CovResult {
                         lines,
                         branches,
-                        functions: _,
+                        functions: records,
                     },
                 )|
                  -> eyre::Result<_> {
-                    #[allow(unused_imports)]
                     use crate::parsing::{
-                        extract_functions, FUNCTION_IN_PROGRAM_MODULE_QUERY,
-                        FUNCTION_QUERY,
+                        extract_captures, extract_functions, extract_test_items, ParsedFile,
                     };
 
-                    let query: &tree_sitter::Query = match opt.contract_style {
-                        ContractStyle::Anchor => {
-                            &FUNCTION_IN_PROGRAM_MODULE_QUERY
-                        }
-                        ContractStyle::Native => &FUNCTION_QUERY,
+                    // NOTE: the lines of the test-only items and of the nodes captured by the
+                    //       exclusion queries (`Point`'s `row` is 0-indexed while
+                    //       `CovResult`'s `lines` are 1-indexed)
+                    let mut excluded = HashSet::new();
+                    let mut spans = extract_test_items(&ParsedFile::read(&path)?)?;
+                    for query in &excl_queries {
+                        spans.extend(extract_captures(&path, query)?);
+                    }
+                    for (start, end) in spans {
+                        excluded.extend(start.row as u32 + 1..=end.row as u32 + 1);
+                    }
+                    let mut lines = lines;
+                    let mut branches = branches;
+                    lines.retain(|line, _| !excluded.contains(line));
+                    branches.retain(|line, _| !excluded.contains(line));
+
+                    let query = opt.contract_styles.for_path(&path).function_query();
+                    // NOTE: functions without any (non-excluded) lines are not contract code
+                    let has_lines = |range: &(tree_sitter::Point, tree_sitter::Point)| {
+                        lines
+                            .range(range.0.row as u32 + 1..=range.1.row as u32 + 1)
+                            .next()
+                            .is_some()
                     };
 
-                    let source_contract_functions =
-                        extract_functions(&path, query)?;
-
                     let functions: HashMap<String, Function, _> =
-                        source_contract_functions
-                            .into_iter()
-                            .map(|(name, range)| {
-                                // NOTE: `Point`'s `row` and `column` are 0-indexed
-                                //       while `CovResult`'s `lines`' are 1-indexed
-                                let start = range.0.row as u32 + 1;
-                                let executed = lines
-                                    .get(&start)
-                                    .map_or(false, |times| *times > 0);
-
-                                (name, Function { start, executed })
-                            })
-                            .collect();
+                        match opt.function_coverage {
+                            // NOTE: line-based, a function is executed if the line of its
+                            //       signature has hits
+                            FunctionCoverage::Heuristic => {
+                                extract_functions(&path, query)?
+                                    .into_iter()
+                                    .filter(|(_, range)| has_lines(range))
+                                    .map(|(name, range)| {
+                                        // NOTE: `Point`'s `row` and `column` are 0-indexed
+                                        //       while `CovResult`'s `lines`' are 1-indexed
+                                        let start = range.0.row as u32 + 1;
+                                        let executed = lines
+                                            .get(&start)
+                                            .map_or(false, |times| *times > 0);
+
+                                        (name, Function { start, executed })
+                                    })
+                                    .collect()
+                            }
+                            // NOTE: the coverage mapping's function records (one per generic
+                            //       instantiation, closure, trait impl method, ...), only kept
+                            //       if they start within one of the contract functions
+                            FunctionCoverage::Records => {
+                                let spans = extract_functions(&path, query)?
+                                    .into_values()
+                                    .filter(has_lines)
+                                    .map(|(start, end)| {
+                                        start.row as u32 + 1..=end.row as u32 + 1
+                                    })
+                                    .collect::<Vec<_>>();
+
+                                let (functions, counts) =
+                                    merge_records(records, &spans, &record_counts);
+                                execution_counts.extend(
+                                    counts
+                                        .into_iter()
+                                        .map(|(name, count)| ((path.clone(), name), count)),
+                                );
+
+                                functions
+                            }
+                        };
 
                     Ok((
                         path,
@@ -565,6 +712,84 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
             .collect::<eyre::Result<Vec<_>>>()?;
         iterator
     };
+
+    Ok((iterator, execution_counts))
+}
+
+/// Add the execution counts of the function records (`FNDA:<count>,<name>`) of an lcov report
+fn count_records(
+    lcov: &[u8],
+    record_counts: &mut HashMap<String, u64>,
+) {
+    for line in String::from_utf8_lossy(lcov).lines() {
+        let Some((count, name)) = line
+            .strip_prefix("FNDA:")
+            .and_then(|record| record.split_once(','))
+        else {
+            continue;
+        };
+        let Ok(count) = count.parse::<u64>() else {
+            continue;
+        };
+        *record_counts.entry(name.to_string()).or_default() += count;
+    }
+}
+
+/// The coverage mapping's function records (one per generic instantiation, closure, trait impl
+/// method, ...) which start within one of the contract functions' `spans`, by their demangled
+/// name, and how many times each was executed in total (from `record_counts`)
+fn merge_records(
+    records: FunctionMap,
+    spans: &[RangeInclusive<u32>],
+    record_counts: &HashMap<String, u64>,
+) -> (FunctionMap, BTreeMap<String, u64>) {
+    let mut functions = FunctionMap::default();
+    let mut counts = BTreeMap::<String, u64>::new();
+    for (name, record) in records {
+        if !spans.iter().any(|span| span.contains(&record.start)) {
+            continue;
+        }
+
+        let count = record_counts.get(&name).copied().unwrap_or_default();
+        // NOTE: without the hash, so that names are stable across builds (and the
+        //       instantiations of one function with the same generics are merged)
+        let name = format!("{:#}", rustc_demangle::demangle(&name));
+        *counts.entry(name.clone()).or_default() += count;
+        functions
+            .entry(name)
+            .and_modify(|function: &mut Function| {
+                function.executed |= record.executed;
+            })
+            .or_insert(record);
+    }
+
+    // NOTE: uninstantiated generic functions get a record with `_` as their generic
+    //       arguments, which is redundant if the function was instantiated
+    let is_placeholder = |name: &str| {
+        name.contains("<_>")
+            || name.contains("<_,")
+            || name.contains(", _>")
+            || name.contains(", _,")
+    };
+    let instantiated = functions
+        .iter()
+        .filter(|(name, _)| !is_placeholder(name))
+        .map(|(_, function)| function.start)
+        .collect::<HashSet<_>>();
+    functions.retain(|name, function| {
+        !is_placeholder(name) || !instantiated.contains(&function.start)
+    });
+    counts.retain(|name, _| functions.contains_key(name));
+
+    (functions, counts)
+}
+
+/// Write out the aggregated results as all of the requested `output_types`
+pub fn output(opt: Opt, iterator: &[ResultTuple]) -> eyre::Result<()> {
+    let num_threads = num_threads(&opt);
+    let source_root = source_root(&opt);
+    let demangle = !opt.no_demangle;
+
     // NOTE: can very cleanly be defined as a `LazyCell`
     let mut sorted_iterator: Option<Vec<ResultTuple>> = None;
     // sorted_iterator = sorted_iterator.or_else(|| {
@@ -599,7 +824,7 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
         let results = if opt.sort_output_types.contains(output_type) {
             // compute and cache the sorted results if not already used
             sorted_iterator = sorted_iterator.or_else(|| {
-                let mut results = iterator.clone();
+                let mut results = iterator.to_vec();
                 results.sort_by_cached_key(|result| {
                     result.0.display().to_string()
                 });
@@ -607,7 +832,7 @@ pub fn main(opt: Opt) -> eyre::Result<()> {
             });
             sorted_iterator.as_ref().unwrap()
         } else {
-            &iterator
+            iterator
         };
 
         match output_type {
@@ -684,4 +909,58 @@ mod tests {
     fn clap_debug_assert() {
         Opt::command().debug_assert();
     }
+
+    #[test]
+    fn parses_both_coveralls_plus_spellings() {
+        assert_eq!("coveralls+".parse::<OutputType>(), Ok(OutputType::CoverallsPlus));
+        assert_eq!("coveralls-plus".parse::<OutputType>(), Ok(OutputType::CoverallsPlus));
+    }
+
+    #[test]
+    fn merges_function_records() {
+        let lcov = b"\
+SF:/project/programs/counter/src/lib.rs
+FN:10,_ZN7counter9increment17h0123456789abcdefE
+FN:10,_ZN7counter9increment17hfedcba9876543210E
+FN:20,_ZN7counter8transfer17h1111111111111111E
+FN:20,_ZN7counter17transfer$LT$_$GT$17h2222222222222222E
+FN:50,_ZN7counter5other17h3333333333333333E
+FNDA:2,_ZN7counter9increment17h0123456789abcdefE
+FNDA:3,_ZN7counter9increment17hfedcba9876543210E
+FNDA:0,_ZN7counter8transfer17h1111111111111111E
+FNDA:0,_ZN7counter17transfer$LT$_$GT$17h2222222222222222E
+FNDA:7,_ZN7counter5other17h3333333333333333E
+end_of_record
+";
+        let mut record_counts = HashMap::new();
+        count_records(lcov, &mut record_counts);
+        // NOTE: e.g. the same function in another test binary
+        count_records(
+            b"FNDA:1,_ZN7counter9increment17h0123456789abcdefE\n",
+            &mut record_counts,
+        );
+
+        let record = |start, executed| Function { start, executed };
+        let records = FunctionMap::from_iter([
+            ("_ZN7counter9increment17h0123456789abcdefE".to_string(), record(10, true)),
+            ("_ZN7counter9increment17hfedcba9876543210E".to_string(), record(10, false)),
+            ("_ZN7counter8transfer17h1111111111111111E".to_string(), record(20, false)),
+            ("_ZN7counter17transfer$LT$_$GT$17h2222222222222222E".to_string(), record(20, false)),
+            ("_ZN7counter5other17h3333333333333333E".to_string(), record(50, true)),
+        ]);
+
+        let (functions, counts) = merge_records(records, &[10..=15, 20..=30], &record_counts);
+
+        let mut names = functions.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["counter::increment", "counter::transfer"]);
+        assert!(functions["counter::increment"].executed);
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("counter::increment".to_string(), 6),
+                ("counter::transfer".to_string(), 0),
+            ]
+        );
+    }
 }
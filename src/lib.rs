@@ -1,7 +1,10 @@
 pub mod config;
 pub mod config_parsing;
 pub mod coverage;
+pub mod explain;
 pub mod from_grcov;
 pub mod generate;
 pub mod parsing;
+pub mod report;
+pub mod show;
 pub mod util;
@@ -3,19 +3,30 @@ use clap_serde_derive::clap::Parser;
 use zest::{
     config::{Config, Subcommands},
     config_parsing::ParseWithConfigFile,
-    coverage, generate,
+    coverage, explain, generate, report, show,
 };
 
 fn main() -> eyre::Result<()> {
     let Config { command } = Config::parse();
     match command.unwrap_or(Subcommands::Coverage(Default::default())) {
         Subcommands::Coverage(config) => {
-            let config = coverage::Config::parse_with_config_file(Some(config))?;
+            let config = coverage::Config::parse_with_config_file(Some(*config))?;
 
             coverage::run(config)
         }
+        Subcommands::Report(config) => {
+            let config = report::Config::parse_with_config_file(Some(*config))?;
+
+            report::run(config)
+        }
         Subcommands::Generate(config) => {
             generate::run(config)
         }
+        Subcommands::Explain(config) => {
+            explain::run(config)
+        }
+        Subcommands::Show(config) => {
+            show::run(*config)
+        }
     }
 }
@@ -1,11 +1,14 @@
 #![allow(unused, dead_code)]
 
-use std::{collections::BTreeMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
 use eyre::{bail, ContextCompat};
 use itertools::Itertools;
 use lazy_static::lazy_static;
-use tree_sitter::{InputEdit, Language, Parser, Point, Query, QueryCursor};
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
 
 // NOTE: can use `LazyCell` on `Rust` >= 1.80.0, but the `time` crate doesn't compile there
 //       <https://github.com/time-rs/time/issues/693>
@@ -16,6 +19,24 @@ lazy_static! {
     pub static ref LANGUAGE: Language = tree_sitter_rust::language();
 }
 
+/// A file's source along with its syntax tree, for the extractors which run on the same file
+pub struct ParsedFile {
+    source: String,
+    tree: Tree,
+}
+
+impl ParsedFile {
+    pub fn read(file_path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let mut parser = Parser::new();
+        parser.set_language(&LANGUAGE)?;
+
+        let source = std::fs::read_to_string(file_path)?;
+        let tree = parser.parse(&source, None).unwrap();
+
+        Ok(Self { source, tree })
+    }
+}
+
 pub static FUNCTION_QUERY_STR: &str = /* query */
     r#"
 (function_item
@@ -49,42 +70,869 @@ lazy_static! {
         Query::new(&LANGUAGE, FUNCTION_IN_PROGRAM_MODULE_QUERY_STR).unwrap();
 }
 
+pub static PROGRAM_MODULE_QUERY_STR: &str = /* query */
+    r#"
+(
+  (
+    (attribute_item
+      (attribute
+        (identifier) @_program))
+    (mod_item) @scope
+  )
+  (#match? @_program "^program$")
+)
+"#;
+
+lazy_static! {
+    /// All of the functions in the `#[program]` module, including the ones in nested modules
+    /// and `impl` blocks
+    pub static ref PROGRAM_MODULE_QUERY: Query =
+        Query::new(&LANGUAGE, PROGRAM_MODULE_QUERY_STR).unwrap();
+}
+
+/// The functions matched by `query`, keyed by their module/impl-qualified names (e.g.
+/// `processor::Processor::process` or `<Counter as Default>::default`), along with their whole
+/// span (signature and body)
+///
+/// A `name` capture matches the function it names, a `scope` capture matches every function
+/// within the captured node
+///
+/// NOTE: the qualification only goes as far as the file, functions which would still collide
+///       (e.g. `#[cfg]`-ed out duplicates) are numbered by their order in the file, from the
+///       second one on, e.g. `Processor::process#2`
 pub fn extract_functions(
     file_path: impl AsRef<Path>,
     query: &Query,
 ) -> eyre::Result<BTreeMap<String, (Point, Point)>> {
+    if !query
+        .capture_names()
+        .iter()
+        .any(|name| *name == "name" || *name == "scope")
+    {
+        bail!("The query has neither a `name` nor a `scope` capture");
+    }
+
     let mut parser = Parser::new();
     parser.set_language(&LANGUAGE)?;
 
     let source_code = std::fs::read_to_string(file_path)?;
-    let tree = parser.parse(source_code.as_bytes(), None).unwrap();
+    let source = source_code.as_bytes();
+    let tree = parser.parse(source, None).unwrap();
     let root_node = tree.root_node();
 
     let mut query_cursor = QueryCursor::new();
-    let matches =
-        query_cursor.matches(query, root_node, source_code.as_bytes());
+    let matches = query_cursor.matches(query, root_node, source);
 
-    let results = matches
-        .map(|m| -> eyre::Result<_> {
-            let (name, node) = m
-                .captures
-                .iter()
-                .find_map(|capture| {
-                    query
-                        .capture_names()
-                        .get(capture.index as usize)
-                        .filter(|name| **name == "name")
-                        .map(|name| (name, capture.node))
+    let mut function_items = vec![];
+    for m in matches {
+        for capture in m.captures {
+            match query.capture_names()[capture.index as usize] {
+                // NOTE: the `name` is a direct child of the `function_item`
+                "name" => function_items.extend(capture.node.parent()),
+                "scope" => function_items.extend(
+                    descendants(capture.node).filter(|node| node.kind() == "function_item"),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    let mut results = BTreeMap::new();
+    let mut occurrences = HashMap::<String, usize>::new();
+    for node in function_items
+        .into_iter()
+        .unique_by(|node| node.id())
+        .sorted_by_key(|node| node.start_byte())
+    {
+        let name = qualified_name(node, source)?;
+        let occurrence = occurrences.entry(name.clone()).or_default();
+        *occurrence += 1;
+        let name = if *occurrence > 1 {
+            format!("{}#{}", name, occurrence)
+        } else {
+            name
+        };
+
+        results.insert(name, (node.start_position(), node.end_position()));
+    }
+
+    Ok(results)
+}
+
+/// `module::Type::function`, `<Type as Trait>::function`, `Trait::function`, ... (within the file)
+fn qualified_name(
+    function_item: Node,
+    source: &[u8],
+) -> eyre::Result<String> {
+    let text = |node: Node, field: &str| -> Option<String> {
+        let text = node.child_by_field_name(field)?.utf8_text(source).ok()?;
+        // NOTE: without the generics, e.g. `Wrapper<T>` -> `Wrapper`
+        Some(text.split('<').next().unwrap_or(text).trim().to_string())
+    };
+
+    let mut segments = vec![text(function_item, "name").wrap_err("Function without a name")?];
+
+    let mut ancestor = function_item.parent();
+    while let Some(node) = ancestor {
+        let segment = match node.kind() {
+            "mod_item" | "trait_item" | "function_item" => text(node, "name"),
+            "impl_item" => match (text(node, "type"), text(node, "trait")) {
+                (Some(type_), Some(trait_)) => Some(format!("<{} as {}>", type_, trait_)),
+                (type_, _) => type_,
+            },
+            _ => None,
+        };
+        segments.extend(segment);
+        ancestor = node.parent();
+    }
+
+    Ok(segments.into_iter().rev().join("::"))
+}
+
+/// The spans of the nodes captured by `query` (except for the `_`-prefixed captures)
+pub fn extract_captures(
+    file_path: impl AsRef<Path>,
+    query: &Query,
+) -> eyre::Result<Vec<(Point, Point)>> {
+    let mut parser = Parser::new();
+    parser.set_language(&LANGUAGE)?;
+
+    let source_code = std::fs::read_to_string(file_path)?;
+    let source = source_code.as_bytes();
+    let tree = parser.parse(source, None).unwrap();
+    let root_node = tree.root_node();
+
+    let mut query_cursor = QueryCursor::new();
+    let spans = query_cursor
+        .matches(query, root_node, source)
+        .flat_map(|m| m.captures)
+        .filter(|capture| !query.capture_names()[capture.index as usize].starts_with('_'))
+        .map(|capture| (capture.node.start_position(), capture.node.end_position()))
+        .collect();
+
+    Ok(spans)
+}
+
+pub static CUSTOM_ERROR_QUERY_STR: &str = /* query */
+    r#"
+(
+  (call_expression
+    function: [(identifier) (scoped_identifier)] @_function
+    arguments: (arguments . (integer_literal) @code .))
+  (#match? @_function "(^|::)Custom$")
+)
+"#;
+
+lazy_static! {
+    pub static ref CUSTOM_ERROR_QUERY: Query =
+        Query::new(&LANGUAGE, CUSTOM_ERROR_QUERY_STR).unwrap();
+}
+
+/// Anchor's `ERROR_CODE_OFFSET`, the first code of an `#[error_code]` enum without an `offset`
+pub const ANCHOR_ERROR_CODE_OFFSET: u32 = 6000;
+
+/// A custom program error which can be returned by a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomError {
+    /// `Enum::Variant` for `#[error_code]` enums, `Custom(n)` for `ProgramError::Custom(n)` sites
+    pub name: String,
+    pub code: u32,
+    pub position: Point,
+}
+
+/// The variants of the `#[error_code]` enums (Anchor) and the `ProgramError::Custom(n)` calls
+/// with a literal `n` (native) in a file
+pub fn extract_custom_errors(file: &ParsedFile) -> eyre::Result<Vec<CustomError>> {
+    let source = file.source.as_bytes();
+    let tree = &file.tree;
+    let root_node = tree.root_node();
+
+    let mut errors = vec![];
+
+    // NOTE: `#[error_code]` enums can be nested in modules, so the whole tree is walked
+    for node in descendants(root_node) {
+        if node.kind() != "enum_item" {
+            continue;
+        }
+        let Some(offset) = error_code_offset(node, source) else {
+            continue;
+        };
+        let (Some(enum_name), Some(variants)) = (
+            node.child_by_field_name("name"),
+            node.child_by_field_name("body"),
+        ) else {
+            continue;
+        };
+        let enum_name = enum_name.utf8_text(source)?;
+
+        // NOTE: same as Rust's discriminants, explicit values reset the count
+        let mut discriminant = 0;
+        let mut cursor = variants.walk();
+        for variant in variants.named_children(&mut cursor) {
+            if variant.kind() != "enum_variant" {
+                continue;
+            }
+            let Some(name) = variant.child_by_field_name("name") else {
+                continue;
+            };
+            if let Some(value) = variant
+                .child_by_field_name("value")
+                .and_then(|value| parse_integer(value.utf8_text(source).ok()?))
+            {
+                discriminant = value;
+            }
+
+            errors.push(CustomError {
+                name: format!("{}::{}", enum_name, name.utf8_text(source)?),
+                code: offset + discriminant,
+                position: name.start_position(),
+            });
+            discriminant += 1;
+        }
+    }
+
+    let mut query_cursor = QueryCursor::new();
+    for m in query_cursor.matches(&CUSTOM_ERROR_QUERY, root_node, source) {
+        let Some(code) = m.captures.iter().find(|capture| {
+            CUSTOM_ERROR_QUERY.capture_names()[capture.index as usize] == "code"
+        }) else {
+            continue;
+        };
+        let Some(value) = parse_integer(code.node.utf8_text(source)?) else {
+            continue;
+        };
+
+        errors.push(CustomError {
+            name: format!("Custom({})", value),
+            code: value,
+            position: code.node.start_position(),
+        });
+    }
+
+    errors.sort_by_key(|error| (error.position.row, error.position.column));
+
+    Ok(errors)
+}
+
+/// The `offset` of an enum's `#[error_code]`/`#[error_code(offset = N)]` attribute, `None` if it
+/// has no such attribute
+fn error_code_offset(
+    enum_item: Node,
+    source: &[u8],
+) -> Option<u32> {
+    let mut sibling = enum_item.prev_named_sibling();
+    while let Some(node) = sibling.filter(|node| {
+        matches!(node.kind(), "attribute_item" | "line_comment" | "block_comment")
+    }) {
+        sibling = node.prev_named_sibling();
+
+        let Some(attribute) = node.named_child(0).filter(|_| node.kind() == "attribute_item")
+        else {
+            continue;
+        };
+        let is_error_code = attribute
+            .named_child(0)
+            .and_then(|path| path.utf8_text(source).ok())
+            .map_or(false, |path| {
+                path == "error_code" || path.ends_with("::error_code")
+            });
+        if !is_error_code {
+            continue;
+        }
+
+        let offset = attribute
+            .child_by_field_name("arguments")
+            .and_then(|arguments| arguments.utf8_text(source).ok())
+            .and_then(|arguments| {
+                let (_, value) = arguments.split_once("offset")?;
+                let value = value.trim_start().strip_prefix('=')?;
+                parse_integer(value.trim_matches(|c: char| c.is_whitespace() || c == ')'))
+            });
+
+        return Some(offset.unwrap_or(ANCHOR_ERROR_CODE_OFFSET));
+    }
+
+    None
+}
+
+/// `123`, `0x7b`, `1_000`, `123u32`
+fn parse_integer(literal: &str) -> Option<u32> {
+    let literal = literal.replace('_', "");
+    let literal = literal.as_str();
+    let literal = ["u8", "u16", "u32", "u64", "usize", "i32", "i64"]
+        .iter()
+        .find_map(|suffix| literal.strip_suffix(suffix))
+        .unwrap_or(literal);
+
+    match literal.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => literal.parse().ok(),
+    }
+}
+
+/// Names of the functions which dispatch a native program's instructions
+pub const PROCESSOR_FUNCTION_NAMES: [&str; 2] = ["process_instruction", "process"];
+
+/// A `match` arm dispatching a single variant of a native program's instruction enum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DispatchArm {
+    pub variant: String,
+    /// Span of the whole arm (pattern and body)
+    pub span: (Point, Point),
+    /// Names (without their paths) of the functions called in the arm's body
+    pub calls: Vec<String>,
+}
+
+/// The `match` on the instruction enum in a native program's processor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionDispatch {
+    /// Without its module path, which may differ between arms
+    pub enum_name: String,
+    pub arms: Vec<DispatchArm>,
+}
+
+/// Find the `match` over the (deserialized) instruction enum in the processor functions (see
+/// `PROCESSOR_FUNCTION_NAMES`) of a file
+///
+/// The instruction enum is the one most of the arms' patterns (`Enum::Variant`,
+/// `Enum::Variant(..)`, `Enum::Variant { .. }`) are paths into
+pub fn extract_instruction_dispatch(
+    file: &ParsedFile,
+) -> eyre::Result<Option<InstructionDispatch>> {
+    let source = file.source.as_bytes();
+    let tree = &file.tree;
+
+    let processors = descendants(tree.root_node()).filter(|node| {
+        node.kind() == "function_item"
+            && node
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(source).ok())
+                .map_or(false, |name| PROCESSOR_FUNCTION_NAMES.contains(&name))
+    });
+
+    let dispatches = processors
+        .flat_map(|processor| {
+            descendants(processor).filter(|node| node.kind() == "match_expression")
+        })
+        .filter_map(|match_expression| {
+            let body = match_expression.child_by_field_name("body")?;
+            let mut cursor = body.walk();
+            let arms = body
+                .named_children(&mut cursor)
+                .filter(|arm| arm.kind() == "match_arm")
+                .filter_map(|arm| {
+                    let path = arm_path(arm, source)?;
+                    let (enum_path, variant) = path.rsplit_once("::")?;
+                    let enum_name = enum_path
+                        .rsplit_once("::")
+                        .map_or(enum_path, |(_, name)| name);
+                    Some((
+                        enum_name.to_string(),
+                        DispatchArm {
+                            variant: variant.to_string(),
+                            span: (arm.start_position(), arm.end_position()),
+                            calls: arm
+                                .child_by_field_name("value")
+                                .map_or(vec![], |value| called_functions(value, source)),
+                        },
+                    ))
                 })
-                .wrap_err("Could not find `name` capture")?;
+                .collect::<Vec<_>>();
 
-            let start = node.start_position();
-            let end = node.end_position();
-            let text = node.utf8_text(source_code.as_bytes()).unwrap();
+            let enum_name = arms
+                .iter()
+                .map(|(enum_name, _)| enum_name)
+                .counts()
+                .into_iter()
+                .max_by_key(|(_, count)| *count)?
+                .0
+                .clone();
+            let arms = arms
+                .into_iter()
+                .filter(|(name, _)| *name == enum_name)
+                .map(|(_, arm)| arm)
+                .collect::<Vec<_>>();
 
-            Ok((text.to_string(), (start, end)))
+            Some(InstructionDispatch { enum_name, arms })
         })
-        .try_collect()?;
+        .collect::<Vec<_>>();
 
-    Ok(results)
+    // NOTE: the outermost (first) match with the most dispatched variants
+    Ok(dispatches
+        .into_iter()
+        .rev()
+        .max_by_key(|dispatch| dispatch.arms.len()))
+}
+
+/// Names of the functions called in `node`, without their paths (e.g. `process_increment` for
+/// `Processor::process_increment(..)`), in order of appearance
+fn called_functions(
+    node: Node,
+    source: &[u8],
+) -> Vec<String> {
+    descendants(node)
+        .filter(|node| node.kind() == "call_expression")
+        .filter_map(|call| {
+            let mut function = call.child_by_field_name("function")?;
+            // NOTE: e.g. `process::<T>(..)`
+            if function.kind() == "generic_function" {
+                function = function.child_by_field_name("function")?;
+            }
+            let name = match function.kind() {
+                "identifier" => function,
+                "scoped_identifier" => function.child_by_field_name("name")?,
+                // NOTE: method calls
+                "field_expression" => function.child_by_field_name("field")?,
+                _ => return None,
+            };
+            name.utf8_text(source).ok()
+        })
+        // NOTE: tuple structs and variants, e.g. `Ok(..)`
+        .filter(|name| !name.starts_with(char::is_uppercase))
+        .unique()
+        .map(str::to_string)
+        .collect()
+}
+
+/// The path of a `match` arm's pattern, e.g. `Enum::Variant` for `Enum::Variant { amount }`
+fn arm_path(
+    arm: Node,
+    source: &[u8],
+) -> Option<String> {
+    let pattern = arm.child_by_field_name("pattern")?;
+    // NOTE: `match_pattern` wraps the actual pattern (and the optional `if` guard)
+    let pattern = match pattern.kind() {
+        "match_pattern" => pattern.named_child(0)?,
+        _ => pattern,
+    };
+    let path = match pattern.kind() {
+        "scoped_identifier" => pattern,
+        "tuple_struct_pattern" | "struct_pattern" => pattern.child_by_field_name("type")?,
+        _ => return None,
+    };
+
+    path.utf8_text(source).ok().map(str::to_string)
+}
+
+/// The variants (and their positions) of each of the enums in a file, keyed by the enum's name
+pub fn extract_enums(
+    file: &ParsedFile,
+) -> eyre::Result<BTreeMap<String, Vec<(String, Point)>>> {
+    let source = file.source.as_bytes();
+    let tree = &file.tree;
+
+    let mut enums = BTreeMap::<String, Vec<(String, Point)>>::new();
+    for node in descendants(tree.root_node()).filter(|node| node.kind() == "enum_item") {
+        let (Some(name), Some(body)) = (
+            node.child_by_field_name("name"),
+            node.child_by_field_name("body"),
+        ) else {
+            continue;
+        };
+
+        let mut cursor = body.walk();
+        let variants = body
+            .named_children(&mut cursor)
+            .filter(|variant| variant.kind() == "enum_variant")
+            .filter_map(|variant| variant.child_by_field_name("name"))
+            .filter_map(|name| {
+                Some((name.utf8_text(source).ok()?.to_string(), name.start_position()))
+            });
+        enums
+            .entry(name.utf8_text(source)?.to_string())
+            .or_default()
+            .extend(variants);
+    }
+
+    Ok(enums)
+}
+
+/// The spans of the test-only items, i.e. the `#[cfg(test)]` (or `#[cfg(all(test, ...))]`, ...)
+/// items and the `#[test]` (or `#[tokio::test]`, ...) functions, including their attributes
+///
+/// A file with a `#![cfg(test)]` inner attribute is test-only as a whole
+pub fn extract_test_items(file: &ParsedFile) -> eyre::Result<Vec<(Point, Point)>> {
+    let source = file.source.as_bytes();
+    let tree = &file.tree;
+
+    /// The (comma-separated) predicates in a `cfg` token tree, e.g. `test` and
+    /// `feature = "bench"` in `(test, feature = "bench")`
+    fn predicates(token_tree: Node) -> Vec<Vec<Node>> {
+        let mut cursor = token_tree.walk();
+        let tokens = token_tree
+            .children(&mut cursor)
+            .filter(|token| !matches!(token.kind(), "(" | ")"))
+            .collect::<Vec<_>>();
+        tokens
+            .split(|token| token.kind() == ",")
+            .filter(|predicate| !predicate.is_empty())
+            .map(<[Node]>::to_vec)
+            .collect()
+    }
+
+    /// Whether a `cfg` predicate only holds when compiling tests: `test`, `all(..)` with such a
+    /// predicate, `any(..)` with only such predicates (never `not(..)`)
+    fn is_test_predicate(
+        predicate: &[Node],
+        source: &[u8],
+    ) -> bool {
+        match predicate {
+            [atom] => atom.utf8_text(source) == Ok("test"),
+            [operator, arguments] if arguments.kind() == "token_tree" => {
+                let predicates = predicates(*arguments);
+                match operator.utf8_text(source) {
+                    Ok("all") => predicates
+                        .iter()
+                        .any(|predicate| is_test_predicate(predicate, source)),
+                    Ok("any") => {
+                        !predicates.is_empty()
+                            && predicates
+                                .iter()
+                                .all(|predicate| is_test_predicate(predicate, source))
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    let is_test_attribute = |attribute_item: Node| -> bool {
+        let Some(attribute) = attribute_item.named_child(0) else {
+            return false;
+        };
+        let Some(path) = attribute.named_child(0) else {
+            return false;
+        };
+        let Ok(path) = path.utf8_text(source) else {
+            return false;
+        };
+        match attribute.child_by_field_name("arguments") {
+            Some(arguments) if path == "cfg" => predicates(arguments)
+                .first()
+                .map_or(false, |predicate| is_test_predicate(predicate, source)),
+            _ => path == "test" || path.ends_with("::test"),
+        }
+    };
+
+    let mut spans = vec![];
+    for node in descendants(tree.root_node()) {
+        match node.kind() {
+            "inner_attribute_item" if is_test_attribute(node) => {
+                if let Some(parent) = node.parent() {
+                    spans.push((parent.start_position(), parent.end_position()));
+                }
+            }
+            "attribute_item" if is_test_attribute(node) => {
+                // NOTE: the attributes are siblings of the item they are applied to (possibly
+                //       along with other attributes and comments in between)
+                let mut item = node.next_named_sibling();
+                while let Some(sibling) = item.filter(|sibling| {
+                    sibling.kind() == "attribute_item" || sibling.kind().ends_with("comment")
+                }) {
+                    item = sibling.next_named_sibling();
+                }
+                if let Some(item) = item {
+                    spans.push((node.start_position(), item.end_position()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(spans)
+}
+
+/// All of the named nodes under `node` (including itself), in pre-order
+fn descendants(node: Node) -> impl Iterator<Item = Node> {
+    let mut stack = vec![node];
+    std::iter::from_fn(move || {
+        let node = stack.pop()?;
+        let mut cursor = node.walk();
+        let children = node.named_children(&mut cursor).collect::<Vec<_>>();
+        stack.extend(children.into_iter().rev());
+        Some(node)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_custom_errors() {
+        let source = r#"
+#[error_code]
+pub enum CounterError {
+    #[msg("Overflow")]
+    Overflow,
+    Underflow,
+}
+
+#[error_code(offset = 100)]
+pub enum VaultError {
+    Locked = 5,
+    Empty,
+}
+
+fn process() -> ProgramResult {
+    Err(ProgramError::Custom(123))
+}
+"#;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), source).unwrap();
+
+        let errors = extract_custom_errors(&ParsedFile::read(file.path()).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|error| (error.name, error.code))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            errors,
+            vec![
+                ("CounterError::Overflow".to_string(), 6000),
+                ("CounterError::Underflow".to_string(), 6001),
+                ("VaultError::Locked".to_string(), 105),
+                ("VaultError::Empty".to_string(), 106),
+                ("Custom(123)".to_string(), 123),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_instruction_dispatch() {
+        let source = r#"
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = CounterInstruction::try_from_slice(instruction_data)?;
+    match instruction {
+        CounterInstruction::Initialize => initialize(accounts),
+        CounterInstruction::Increment { amount } if amount > 0 => {
+            match amount.checked_add(1) {
+                Some(_) => increment(accounts, amount),
+                None => Err(ProgramError::InvalidArgument),
+            }
+        }
+        instruction::CounterInstruction::Close(args) => close(accounts, args),
+        CounterInstruction::Reset(..) => reset(accounts),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+"#;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), source).unwrap();
+
+        let dispatch = extract_instruction_dispatch(&ParsedFile::read(file.path()).unwrap())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(dispatch.enum_name, "CounterInstruction");
+        assert_eq!(
+            dispatch
+                .arms
+                .iter()
+                .map(|arm| (arm.variant.as_str(), arm.span.0.row))
+                .collect::<Vec<_>>(),
+            vec![("Initialize", 8), ("Increment", 9), ("Close", 15), ("Reset", 16)]
+        );
+        assert_eq!(
+            dispatch
+                .arms
+                .iter()
+                .map(|arm| arm.calls.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                vec!["initialize"],
+                vec!["checked_add", "increment"],
+                vec!["close"],
+                vec!["reset"],
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_enums() {
+        let source = r#"
+pub enum CounterInstruction {
+    Initialize,
+    Increment { amount: u64 },
+}
+
+mod state {
+    enum Status { Open, Closed(u8) }
+}
+"#;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), source).unwrap();
+
+        let enums = extract_enums(&ParsedFile::read(file.path()).unwrap()).unwrap();
+
+        assert_eq!(
+            enums
+                .iter()
+                .map(|(name, variants)| (
+                    name.as_str(),
+                    variants
+                        .iter()
+                        .map(|(variant, position)| (variant.as_str(), position.row))
+                        .collect::<Vec<_>>()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("CounterInstruction", vec![("Initialize", 2), ("Increment", 3)]),
+                ("Status", vec![("Open", 7), ("Closed", 7)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_qualified_functions() {
+        let source = r#"
+#[program]
+pub mod counter {
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        helpers::check(&ctx)
+    }
+
+    mod helpers {
+        pub fn check(ctx: &Context<Initialize>) -> Result<()> {
+            Ok(())
+        }
+    }
+}
+
+pub struct Processor;
+
+impl Processor {
+    pub fn process() {}
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        fn inner() {}
+        Processor
+    }
+}
+
+trait Instruction {
+    fn process() {}
+}
+
+#[cfg(feature = "a")]
+fn feature() {}
+#[cfg(not(feature = "a"))]
+fn feature() {}
+"#;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), source).unwrap();
+
+        let functions = extract_functions(file.path(), &FUNCTION_QUERY)
+            .unwrap()
+            .into_iter()
+            .map(|(name, (start, end))| (name, start.row, end.row))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            functions,
+            vec![
+                ("<Processor as Default>::default".to_string(), 21, 24),
+                ("<Processor as Default>::default::inner".to_string(), 22, 22),
+                ("Instruction::process".to_string(), 28, 28),
+                ("Processor::process".to_string(), 17, 17),
+                ("counter::helpers::check".to_string(), 8, 10),
+                ("counter::initialize".to_string(), 3, 5),
+                ("feature".to_string(), 32, 32),
+                ("feature#2".to_string(), 34, 34),
+            ]
+        );
+
+        let program_functions = extract_functions(file.path(), &PROGRAM_MODULE_QUERY)
+            .unwrap()
+            .into_keys()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            program_functions,
+            vec!["counter::helpers::check", "counter::initialize"]
+        );
+    }
+
+    #[test]
+    fn extracts_captures() {
+        let source = r#"
+use anchor_lang::prelude::*;
+
+#[account]
+pub struct Counter {
+    pub count: u64,
+}
+
+#[derive(Accounts)]
+pub struct Initialize {}
+"#;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), source).unwrap();
+
+        let query = Query::new(
+            &LANGUAGE,
+            r#"
+((attribute_item (attribute (identifier) @_account)) . (struct_item) @exclude
+  (#eq? @_account "account"))
+"#,
+        )
+        .unwrap();
+        let rows = extract_captures(file.path(), &query)
+            .unwrap()
+            .into_iter()
+            .map(|(start, end)| (start.row, end.row))
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows, vec![(4, 6)]);
+    }
+
+    #[test]
+    fn extracts_test_items() {
+        let source = r#"
+pub fn process() {}
+
+#[cfg(test)]
+#[allow(unused)]
+mod tests {
+    #[test]
+    fn processes() {}
+}
+
+#[tokio::test]
+async fn integration() {}
+
+#[cfg(not(test))]
+fn not_a_test() {}
+
+#[cfg(all(test, feature = "bench"))]
+mod benches {}
+
+#[cfg(any(target_os = "solana", all(not(feature = "test"), test)))]
+fn helper() {}
+
+#[cfg(any(test, all(feature = "bench", test)))]
+fn bench_helper() {}
+
+#[cfg(feature = "test")]
+fn featured() {}
+"#;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), source).unwrap();
+
+        let rows = extract_test_items(&ParsedFile::read(file.path()).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|(start, end)| (start.row, end.row))
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows, vec![(3, 8), (6, 7), (10, 11), (16, 17), (22, 23)]);
+    }
 }
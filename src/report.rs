@@ -0,0 +1,184 @@
+use std::{fs, path::PathBuf};
+
+use clap_serde_derive::{
+    clap::{self, ArgAction},
+    ClapSerde,
+};
+use eyre::{bail, Context};
+use walkdir::WalkDir;
+
+use crate::{
+    config_parsing::ConfigFileName,
+    coverage::{
+        self,
+        per_test::TestMatrix,
+        rustup::install_llvm_tools,
+        test_output,
+        workspace::{project_dir, Workspace},
+        CoverageStrategy,
+    },
+};
+
+/// Same config file (and the same option names) as `zest coverage`, the build and test options
+/// are simply not used
+#[derive(ClapSerde, Debug, Clone)]
+pub struct Config {
+    #[arg(long, help = "Path to the solana project")]
+    #[default(".".into())]
+    pub path: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Path to the `Cargo.toml` of the project (defaults to `Cargo.toml` in `--path`)"
+    )]
+    #[default(None)]
+    pub manifest_path: Option<PathBuf>,
+
+    #[arg(
+        long = "package",
+        short = 'p',
+        value_name = "SPEC",
+        help = "Workspace member to report on (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub packages: Vec<String>,
+
+    #[arg(
+        long = "exclude",
+        value_name = "SPEC",
+        help = "Workspace member to exclude from reporting (can be stacked)"
+    )]
+    #[default(vec![])]
+    pub exclude: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Version of the compiler toolchain whose `llvm-tools` to use"
+    )]
+    #[default(None)]
+    pub compiler_version: Option<String>,
+
+    #[arg(
+        long,
+        action(ArgAction::SetTrue),
+        help = "Whether to report branch coverage (the profile data must have been recorded with `--branch`)"
+    )]
+    #[default(false)]
+    pub branch: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Coverage strategy the profile data was recorded with"
+    )]
+    #[default(CoverageStrategy::InstrumentCoverage)]
+    pub coverage_strategy: CoverageStrategy,
+
+    #[clap_serde]
+    #[command(flatten)]
+    #[serde(flatten)]
+    pub report: coverage::report::Config,
+}
+
+impl ConfigFileName for Config {
+    const NAME: &'static str = "coverage";
+}
+
+pub fn run(config: Config) -> eyre::Result<()> {
+    with_context(config, coverage::report::run)
+}
+
+/// Find the existing profile data (and the results of the last test run) and pass it on to `f`
+pub fn with_context<T>(
+    config: Config,
+    f: impl FnOnce(coverage::report::Config, coverage::report::Context) -> eyre::Result<T>,
+) -> eyre::Result<T> {
+    let Config {
+        path,
+        manifest_path,
+        packages,
+        exclude,
+        compiler_version,
+        branch,
+        coverage_strategy,
+        report: report_config,
+    } = config;
+
+    if let Err(err) = report_config.validate(branch) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+
+    let manifest_path = manifest_path.unwrap_or_else(|| path.join("Cargo.toml"));
+    let manifest_path = fs::canonicalize(&manifest_path)
+        .with_context(|| format!("Could not find {}", manifest_path.display()))?;
+    let workspace =
+        Workspace::from_manifest_path(&manifest_path, compiler_version.as_ref())?;
+    let selected_packages = workspace.select(&packages, &exclude)?;
+
+    let target_dir = workspace.target_dir.as_path();
+    let coverage_dir = target_dir.join("coverage");
+
+    // NOTE: `.profraw` files are written to `coverage_dir`, `.gcda` files next to the objects
+    let (data_dir, data_ext) = if coverage_strategy.is_source_based() {
+        (coverage_dir.as_path(), "profraw")
+    } else {
+        (target_dir, "gcda")
+    };
+    let has_data = WalkDir::new(data_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| {
+            entry
+                .path()
+                .extension()
+                .map_or(false, |ext| ext == data_ext)
+        });
+    if !has_data {
+        bail!(
+            "No `.{}` files found in {} (run `zest coverage` first)",
+            data_ext,
+            data_dir.display()
+        );
+    }
+
+    install_llvm_tools(compiler_version.as_ref(), project_dir(&manifest_path))?;
+
+    // NOTE: the runs of a `--per-test` coverage are stored by their index in the matrix
+    let per_test_matrix = coverage_dir.join(TestMatrix::FILE_NAME);
+    let per_test_runs = if per_test_matrix.exists() {
+        TestMatrix::read(per_test_matrix)?
+            .tests
+            .into_iter()
+            .enumerate()
+            .map(|(index, test)| {
+                (test, coverage_dir.join("per_test").join(index.to_string()))
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let failed_tests_file = coverage_dir.join(test_output::FAILED_TESTS_FILE_NAME);
+    let failed_tests = if failed_tests_file.exists() {
+        fs::read_to_string(failed_tests_file)?
+            .lines()
+            .map(str::to_string)
+            .collect()
+    } else {
+        vec![]
+    };
+
+    f(
+        report_config,
+        coverage::report::Context {
+            workspace: &workspace,
+            selected_packages,
+            coverage_strategy,
+            branch,
+            per_test_runs,
+            failed_tests,
+        },
+    )
+}
@@ -0,0 +1,94 @@
+use std::{fs, path::PathBuf};
+
+use clap_serde_derive::clap::{self, Parser};
+use eyre::{bail, Context};
+
+use crate::{
+    config_parsing::{ParseWithConfigFile, WithConfigFile},
+    coverage, explain, from_grcov, report, util,
+};
+
+const RED: &str = "\u{1b}[31m";
+const YELLOW: &str = "\u{1b}[33m";
+const RESET: &str = "\u{1b}[39m";
+
+#[derive(Parser)]
+pub struct Config {
+    #[arg(
+        value_name = "FILE",
+        help = "Source file to show, e.g. `programs/counter/src/lib.rs`"
+    )]
+    pub file: PathBuf,
+
+    /// Same options as `zest report` (only the ones used for aggregating are relevant)
+    #[command(flatten)]
+    pub report: WithConfigFile<report::Config>,
+}
+
+pub fn run(config: Config) -> eyre::Result<()> {
+    let Config { file, report } = config;
+    let config = report::Config::parse_with_config_file(Some(report))?;
+
+    report::with_context(config, |report_config, context| {
+        let opt = coverage::report::grcov_opt(&report_config, &context)?;
+        // NOTE: only aggregated, none of the reports are written
+        let (results, _) = from_grcov::aggregate(&opt)?;
+
+        let rel_path = explain::relative_to_root(&file, &context.workspace.root);
+        let Some((path, _, result)) = results.iter().find(|(_, other, _)| *other == rel_path)
+        else {
+            bail!(
+                "{} is not part of the coverage data (not compiled, ignored or excluded)",
+                rel_path.display()
+            );
+        };
+
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        let colors = util::use_colors(&std::io::stdout());
+        let color = |color: &'static str| if colors { color } else { "" };
+        let reset = if colors { RESET } else { "" };
+
+        println!("{}", rel_path.display());
+        println!("{:>6} {:>8} {:>7} |", "Line", "Hits", "Branch");
+        for (index, text) in source.lines().enumerate() {
+            // NOTE: `CovResult`'s `lines` are 1-indexed
+            let line = index as u32 + 1;
+            let hits = result.lines.get(&line);
+            let branches = result.branches.get(&line);
+
+            let hits_column = hits.map_or_else(String::new, u64::to_string);
+            let branches_column = branches.map_or_else(String::new, |taken| {
+                format!(
+                    "{}/{}",
+                    taken.iter().filter(|taken| **taken).count(),
+                    taken.len()
+                )
+            });
+            let highlight = match (hits, branches) {
+                (Some(0), _) => color(RED),
+                (_, Some(taken)) if taken.iter().any(|taken| !taken) => color(YELLOW),
+                _ => "",
+            };
+            // NOTE: without colors, the uncovered lines and branches are marked in the gutter
+            let marker = match (hits, branches) {
+                (Some(0), _) => "!",
+                (_, Some(taken)) if taken.iter().any(|taken| !taken) => "?",
+                _ => " ",
+            };
+
+            println!(
+                "{:>6} {:>8} {:>7}{}|{}{}{}",
+                line,
+                hits_column,
+                branches_column,
+                marker,
+                highlight,
+                text,
+                if highlight.is_empty() { "" } else { reset },
+            );
+        }
+
+        Ok(())
+    })
+}
@@ -1,5 +1,5 @@
 use eyre::{Context, Result};
-use std::{fs, os::unix, path::Path};
+use std::{fs, io::IsTerminal, os::unix, path::Path};
 
 #[rustfmt::skip]
 pub fn remove_contents(path: impl AsRef<Path>) -> Result<()> {
@@ -46,3 +46,8 @@ pub fn to_option<A>(predicate: bool, value: A) -> Option<A> {
         None
     }
 }
+
+/// Whether to color the output written to `stream` (a terminal, unless `NO_COLOR` is set)
+pub fn use_colors(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}